@@ -0,0 +1,154 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// A labeled region of the file. Bookmarks are annotations without a length. Positions are
+/// offsets within the file (not within the loaded window), such that annotations stay valid
+/// when only a part of the file is loaded.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Annotation {
+    pub start: u64,
+    pub length: u64,
+    pub label: String,
+    pub color: [u8; 3],
+}
+
+impl Annotation {
+    pub fn is_bookmark(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Whether the annotation covers the byte at the given file offset. Bookmarks cover the
+    /// byte they point to.
+    pub fn contains(&self, offset: u64) -> bool {
+        offset >= self.start && offset - self.start < self.length.max(1)
+    }
+}
+
+/// The file in which the annotations of the given input file are stored.
+pub fn sidecar_path(filename: &str) -> PathBuf {
+    PathBuf::from(format!("{}.binocle-annotations", filename))
+}
+
+/// Read the annotations from a sidecar file. A missing file means that there are no
+/// annotations yet.
+pub fn load(path: &Path) -> Result<Vec<Annotation>> {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            parse(&content).with_context(|| format!("Invalid file '{}'", path.display()))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e).with_context(|| format!("Could not read '{}'", path.display())),
+    }
+}
+
+/// Write the annotations to a sidecar file. The file is removed if there are none left.
+pub fn save(path: &Path, annotations: &[Annotation]) -> io::Result<()> {
+    if annotations.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    fs::write(path, format(annotations))
+}
+
+/// One annotation per line: start (hex), length, color and label, separated by whitespace.
+/// The label extends to the end of the line.
+///
+/// ```text
+/// 0x1a0 64 #ff8000 section header table
+/// ```
+fn format(annotations: &[Annotation]) -> String {
+    annotations
+        .iter()
+        .map(|annotation| {
+            let [r, g, b] = annotation.color;
+            let line = format!(
+                "0x{:x} {} #{:02x}{:02x}{:02x} {}",
+                annotation.start,
+                annotation.length,
+                r,
+                g,
+                b,
+                annotation.label.replace('\n', " ")
+            );
+            format!("{}\n", line.trim_end())
+        })
+        .collect()
+}
+
+fn parse(content: &str) -> Result<Vec<Annotation>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| parse_line(line).with_context(|| format!("line {}", i + 1)))
+        .collect()
+}
+
+fn parse_line(line: &str) -> Result<Annotation> {
+    let mut fields = line.trim().splitn(4, char::is_whitespace);
+    let mut next = |name| fields.next().ok_or_else(|| anyhow!("missing {}", name));
+
+    let start = next("start")?;
+    let start = u64::from_str_radix(start.trim_start_matches("0x"), 16)
+        .with_context(|| format!("invalid start '{}'", start))?;
+    let length = next("length")?;
+    let length = length
+        .parse()
+        .with_context(|| format!("invalid length '{}'", length))?;
+    if start.checked_add(length).is_none() {
+        bail!("region extends beyond the largest possible offset");
+    }
+    let color = next("color")?;
+    let color = match color
+        .strip_prefix('#')
+        .map(|hex| u32::from_str_radix(hex, 16))
+    {
+        Some(Ok(rgb)) if color.len() == 7 => [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+        _ => bail!("invalid color '{}'", color),
+    };
+    let label = fields.next().unwrap_or_default().trim().to_string();
+
+    Ok(Annotation {
+        start,
+        length,
+        label,
+        color,
+    })
+}
+
+#[test]
+fn annotations_roundtrip() {
+    let annotations = vec![
+        Annotation {
+            start: 0x1a0,
+            length: 64,
+            label: "section header table".into(),
+            color: [255, 128, 0],
+        },
+        Annotation {
+            start: 0,
+            length: 0,
+            label: String::new(),
+            color: [0, 0, 255],
+        },
+    ];
+    let content = format(&annotations);
+    assert_eq!(
+        content,
+        "0x1a0 64 #ff8000 section header table\n0x0 0 #0000ff\n"
+    );
+    assert_eq!(parse(&content).unwrap(), annotations);
+
+    assert!(parse("0x10 4 red label").is_err());
+    assert!(parse("0x10").is_err());
+    assert!(parse("0xffffffffffffffff 16 #ff0000 x").is_err());
+    assert!(annotations[1].contains(0));
+    assert!(!annotations[1].contains(1));
+    assert!(annotations[0].contains(0x1df));
+    assert!(!annotations[0].contains(0x1e0));
+}
@@ -1,61 +1,640 @@
-use anyhow::Result;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-use crate::buffer::Buffer;
+use anyhow::{Context, Result};
+use clap::Parser;
+use log::{error, warn};
+use rayon::prelude::*;
+
+use crate::annotations::{self, Annotation};
+use crate::buffer::{is_named_pipe, Buffer, Window};
 use crate::datatype::Datatype;
+use crate::decompress::{decompress, Compression};
+use crate::follow::{changed_ranges, FileWatcher};
+use crate::inspector::Inspection;
+use crate::minimap::Minimap;
 use crate::options::{BackingOption, CliOptions};
-use crate::settings::{GuiDatatype, PixelStyle, Settings, WIDTH};
+use crate::period::{candidate_periods, Period};
+use crate::pixel_format::rank_formats;
+use crate::plot::{Digraph, Trigram};
+use crate::search::{compile, Search, SearchMessage, MAX_MATCHES};
+use crate::selection::{format_c_array, format_hex, Selection, SelectionAction};
+use crate::session::{Session, SessionAction};
+use crate::settings::{CanvasMode, CanvasState, GuiDatatype, PixelStyle, Settings};
 use crate::style::{
-    Abgr, Bgr, Category, ColorGradient, Colorful, DatatypeStyle, Entropy, Grayscale, Rgb, Rgba,
-    Style,
+    Abgr, Bgr, Bits, Category, Color, ColorGradient, Colorful, DatatypeStyle, Entropy, Grayscale,
+    Rgb, Rgb565, Rgba, Scratch, Style,
 };
 use crate::view::View;
 
+/// At most this many visible bytes are analyzed to detect the width
+const MAX_AUTO_WIDTH_SAMPLE: u64 = 1 << 18;
+
+/// At most this many selected bytes are analyzed to guess the pixel format
+const MAX_FORMAT_SAMPLE: u64 = 1 << 20;
+
+/// Larger selections can only be saved to a file, not copied to the clipboard
+const MAX_COPY_LENGTH: u64 = 1 << 20;
+
 pub struct Binocle {
     pub settings: Settings,
     buffer: Buffer,
+    filename: String,
+    compression: Option<Compression>,
+    window: Window,
+    watcher: Option<FileWatcher>,
+    /// Byte ranges that have changed with the last update of the buffer
+    changed: Vec<Range<usize>>,
+    minimap: Minimap,
+    search: Option<Search>,
+    /// Sidecar file in which the annotations are stored, if there is one
+    annotations_path: Option<PathBuf>,
+    saved_annotations_version: u64,
+    /// Incremented whenever the contents of the buffer change
+    generation: u64,
+    /// State of the last drawn frame and hex view, used to skip redundant updates
+    drawn: Option<(CanvasState, u64)>,
+    hex_view_state: Option<(CanvasState, u64)>,
 }
 
 impl Binocle {
     pub fn new(options: CliOptions) -> Result<Self> {
-        let buffer = match options.backing {
-            BackingOption::File => Buffer::from_file(options.filename),
-            BackingOption::Mmap => Buffer::from_mmap(options.filename),
-        }?;
+        let session = options.session.as_deref().map(Session::load).transpose()?;
+        let filename = match (options.filename, &session) {
+            (Some(filename), _) => filename,
+            (None, Some(session)) => session.input.to_string_lossy().into_owned(),
+            (None, None) => String::new(),
+        };
+        let window = Window {
+            offset: options.load_offset.unwrap_or(0),
+            length: options.load_length,
+        };
+        let live_input = filename == "-" || is_named_pipe(&filename);
+        let follow = options.follow && !live_input;
+        // Files that are being followed are always read into memory. Accessing a memory mapping
+        // of a file that is truncated in the meantime would crash.
+        let backing = if follow {
+            BackingOption::File
+        } else {
+            options.backing
+        };
+
+        let mut compression = if options.pid.is_none() && !live_input && !options.no_decompress {
+            Compression::detect_file(&filename)?
+        } else {
+            None
+        };
+
+        let mut decompression_error = None;
+        let mut buffer = if let Some(pid) = options.pid {
+            Buffer::from_process(pid).with_context(|| {
+                format!(
+                    "Could not read the memory of process {} (is it running and are you allowed \
+                     to trace it?)",
+                    pid
+                )
+            })?
+        } else if filename == "-" {
+            Buffer::from_stream(io::stdin(), window)
+        } else if live_input {
+            Buffer::from_stream(File::open(&filename)?, window)
+        } else {
+            match open_file(&filename, backing, compression, window) {
+                Err(e) if compression.is_some() => {
+                    // The file only looks like compressed data
+                    let note = format!(
+                        "Could not decompress the file as {} ({}), showing the raw data",
+                        compression.map_or("", |c| c.name()),
+                        e
+                    );
+                    warn!("{}", note);
+                    decompression_error = Some(note);
+                    compression = None;
+                    open_file(&filename, backing, None, window)?
+                }
+                result => result?,
+            }
+        };
+
+        if options.output.is_some() {
+            // Without an event loop, there is nobody to pick up streamed data as it arrives
+            buffer.poll(true)?;
+        }
+
+        let watcher = if follow {
+            Some(FileWatcher::new(&filename))
+        } else {
+            None
+        };
 
         let buffer_length = buffer.len();
-        let settings = Settings {
-            buffer_length: buffer_length as isize,
+        let mut settings = Settings {
+            buffer_length,
+            base_offset: window.offset,
+            live_input: live_input || watcher.is_some(),
+            compression: compression.map(|c| c.name()),
+            decompression_error,
+            compressed_length: if compression.is_some() {
+                std::fs::metadata(&filename)?.len()
+            } else {
+                0
+            },
+            export_path: if options.pid.is_some() || filename == "-" {
+                "selection.bin".into()
+            } else {
+                format!("{}.selection.bin", filename)
+            },
+            session_path: match &options.session {
+                Some(path) => path.to_string_lossy().into_owned(),
+                None if options.pid.is_some() || filename == "-" => "view.binocle-session".into(),
+                None => format!("{}.binocle-session", filename),
+            },
             ..Default::default()
         };
 
-        Ok(Self { buffer, settings })
+        if let Buffer::ProcessBuffer(process) = &buffer {
+            settings.mappings = process.memory.mappings.iter().map(|m| m.label()).collect();
+            settings.selected_mapping = process.selected;
+        }
+
+        if let Some(session) = &session {
+            session.apply(&mut settings)?;
+        }
+
+        if let Some(bits) = options.bits {
+            settings.set_bit_order(Some(bits.into()));
+        }
+        if let Some(width) = options.width {
+            settings.width = width;
+        }
+        if let Some(stride) = options.stride {
+            settings.stride = stride;
+        }
+        if let Some(offset) = options.offset {
+            settings.offset = offset.saturating_sub(window.offset) * settings.units_per_byte();
+        }
+        if let Some(zoom) = options.zoom {
+            settings.zoom = zoom;
+        }
+        if let Some(datatype) = options.datatype {
+            settings.datatype_settings.datatype = datatype.into();
+            settings.pixel_style = PixelStyle::Datatype;
+        }
+        if let Some(signedness) = options.signedness {
+            settings.datatype_settings.signedness = signedness.into();
+        }
+        if let Some(endianness) = options.endianness {
+            settings.datatype_settings.endianness = endianness.into();
+        }
+        if let Some(value_range) = options.value_range {
+            settings.value_range = value_range;
+        }
+        if let Some(style) = options.style {
+            settings.pixel_style = style.into();
+        }
+        settings.hex_view_visible |= options.hex_view;
+        settings.clamp();
+
+        let mut annotations_path = None;
+        if options.pid.is_none() && filename != "-" {
+            let path = annotations::sidecar_path(&filename);
+            match annotations::load(&path) {
+                Ok(annotations) => {
+                    settings.annotations = annotations;
+                    annotations_path = Some(path);
+                }
+                Err(e) => {
+                    // Do not overwrite a file that we could not read
+                    error!("Failed to load annotations: {:#}", e);
+                    settings.annotations_status = format!("{:#}", e);
+                }
+            }
+        }
+
+        Ok(Self {
+            buffer,
+            settings,
+            filename,
+            compression,
+            window,
+            watcher,
+            changed: vec![],
+            minimap: Minimap::default(),
+            search: None,
+            annotations_path,
+            saved_annotations_version: 0,
+            generation: 0,
+            drawn: None,
+            hex_view_state: None,
+        })
+    }
+
+    /// Pick up newly arrived data for streamed input, reload followed files if they have been
+    /// modified and switch to another process memory mapping if one has been selected. Returns
+    /// `true` if the buffer has changed.
+    pub fn poll_buffer(&mut self) -> Result<bool> {
+        let previous_length = self.buffer.len();
+        let mut changed = false;
+
+        if self.buffer.poll(false)? {
+            let appended = (previous_length as usize)..(self.buffer.len() as usize);
+            self.changed = vec![appended];
+            changed = true;
+        }
+        if self.watcher.is_none() && !self.buffer.is_receiving() {
+            // The stream has ended, nothing will change anymore
+            self.settings.live_input = false;
+        }
+
+        if let Some(watcher) = &mut self.watcher {
+            if watcher.poll() {
+                let buffer = open_file(
+                    &self.filename,
+                    BackingOption::File,
+                    self.compression,
+                    self.window,
+                )?;
+                self.changed = changed_ranges(self.buffer.data(), buffer.data());
+                self.buffer = buffer;
+                self.minimap = Minimap::default();
+                changed = true;
+            }
+        }
+
+        if let (Buffer::ProcessBuffer(process), Some(index)) =
+            (&mut self.buffer, self.settings.selected_mapping)
+        {
+            if process.selected != Some(index) {
+                process.select(index);
+                self.changed.clear();
+                self.minimap = Minimap::default();
+                self.settings.offset = 0;
+                self.settings.offset_fine = 0;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.generation += 1;
+            self.settings.buffer_length = self.buffer.len();
+            if self.settings.follow_tail {
+                self.settings.offset = self.settings.end_offset();
+                self.settings.offset_fine = 0;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Open a saved session in the same way as `binocle --session <path>` does.
+    pub fn from_session(path: &Path) -> Result<Self> {
+        let arguments = ["binocle".as_ref(), "--session".as_ref(), path.as_os_str()];
+        Self::new(CliOptions::try_parse_from(arguments)?)
+    }
+
+    /// The input file ('-' for stdin, empty for a process).
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Whether the file is reloaded when it changes on disk.
+    pub fn is_followed(&self) -> bool {
+        self.watcher.is_some()
+    }
+
+    /// Collect the new results of the minimap of the whole buffer. Returns `true` while the
+    /// minimap is being built.
+    pub fn update_minimap(&mut self) -> bool {
+        match self.minimap.update(&self.buffer) {
+            Ok(true) => {
+                self.settings.minimap.clone_from(&self.minimap.entropy);
+                self.settings.minimap_block_size = self.minimap.block_size;
+            }
+            Ok(false) => {}
+            Err(e) => error!("Failed to build minimap: {}", e),
+        }
+        self.minimap.is_running()
+    }
+
+    fn current_state(&self) -> (CanvasState, u64) {
+        (self.settings.canvas_state(), self.generation)
+    }
+
+    /// Force the next frame to be drawn, e.g. because the frame buffer has been replaced.
+    pub fn invalidate(&mut self) {
+        self.drawn = None;
+    }
+
+    /// Whether the settings or the data have changed since the last call to `draw`.
+    pub fn needs_redraw(&self) -> bool {
+        self.drawn.as_ref() != Some(&self.current_state())
+    }
+
+    /// Start a new search if one has been requested and collect the results of the running
+    /// one. Returns `true` while a search is running.
+    pub fn update_search(&mut self) -> bool {
+        let settings = &mut self.settings;
+        if settings.search_requested {
+            settings.search_requested = false;
+            settings.search_matches.clear();
+            settings.search_current = None;
+            settings.search_version += 1;
+
+            self.search = None;
+            match compile(&settings.search_query, settings.search_kind) {
+                Ok(regex) => match self.buffer.snapshot() {
+                    Ok(snapshot) => {
+                        self.search = Some(Search::start(snapshot, regex));
+                        settings.search_status = "searching...".into();
+                    }
+                    Err(e) => settings.search_status = format!("Could not search: {}", e),
+                },
+                Err(e) => settings.search_status = format!("Invalid query: {}", e),
+            }
+        }
+
+        let messages: Vec<SearchMessage> = match &self.search {
+            Some(search) => search.receiver.try_iter().collect(),
+            None => return false,
+        };
+        for message in messages {
+            match message {
+                SearchMessage::Progress { searched, matches } => {
+                    if !matches.is_empty() {
+                        settings.search_matches.extend(matches);
+                        settings.search_version += 1;
+                    }
+                    settings.search_status = format!(
+                        "searching... {}%",
+                        searched * 100 / settings.buffer_length.max(1)
+                    );
+                }
+                SearchMessage::Finished => {
+                    let count = settings.search_matches.len();
+                    settings.search_status = if count >= MAX_MATCHES {
+                        format!("stopped after {} matches", count)
+                    } else {
+                        format!("{} matches", count)
+                    };
+                    self.search = None;
+                }
+                SearchMessage::Failed(e) => {
+                    settings.search_status = format!("Search failed: {}", e);
+                    self.search = None;
+                }
+            }
+        }
+
+        self.search.is_some()
+    }
+
+    /// Write the annotations to the sidecar file if they have changed.
+    pub fn save_annotations(&mut self) {
+        if self.settings.annotations_version == self.saved_annotations_version {
+            return;
+        }
+        self.saved_annotations_version = self.settings.annotations_version;
+
+        let path = match &self.annotations_path {
+            Some(path) => path,
+            None => return,
+        };
+        self.settings.annotations_status = match annotations::save(path, &self.settings.annotations)
+        {
+            Ok(()) => format!("Saved to '{}'", path.display()),
+            Err(e) => format!("Could not save to '{}': {}", path.display(), e),
+        };
+    }
+
+    /// Save or open a session, as requested through the GUI. Sessions for another input file
+    /// cannot be opened in place; their path is returned instead.
+    pub fn handle_session_action(&mut self) -> Option<PathBuf> {
+        let action = self.settings.session_action.take()?;
+        let path = PathBuf::from(&self.settings.session_path);
+
+        let result = match action {
+            SessionAction::Save => {
+                if self.filename.is_empty() || self.filename == "-" {
+                    self.settings.session_status = "Sessions can only be saved for files".into();
+                    return None;
+                }
+                Session::capture(Path::new(&self.filename), &self.settings)
+                    .save(&path)
+                    .with_context(|| format!("Could not save session to '{}'", path.display()))
+                    .map(|()| format!("Saved session to '{}'", path.display()))
+            }
+            SessionAction::Open => match Session::load(&path) {
+                Ok(session) if !is_same_file(&session.input, Path::new(&self.filename)) => {
+                    return Some(path);
+                }
+                Ok(session) => session
+                    .apply(&mut self.settings)
+                    .map(|()| format!("Opened session '{}'", path.display())),
+                Err(e) => Err(e),
+            },
+        };
+        self.settings.session_status = result.unwrap_or_else(|e| format!("{:#}", e));
+        None
+    }
+
+    /// Carry out the action on the selection that has been requested through the GUI.
+    pub fn handle_selection_action(&mut self) {
+        let (action, selection) = match (
+            self.settings.selection_action.take(),
+            self.settings.selection.clone(),
+        ) {
+            (Some(action), Some(selection)) => (action, selection),
+            _ => return,
+        };
+
+        self.settings.selection_status = match action {
+            SelectionAction::Save => {
+                let path = self.settings.export_path.clone();
+                let result = File::create(&path).and_then(|file| {
+                    let mut writer = BufWriter::new(file);
+                    self.read_selection(&selection, |data| writer.write_all(data))?;
+                    writer.flush()
+                });
+                match result {
+                    Ok(()) => format!("Saved {} bytes to '{}'", selection.len(), path),
+                    Err(e) => format!("Could not save selection to '{}': {}", path, e),
+                }
+            }
+            SelectionAction::CopyHex | SelectionAction::CopyCArray
+                if selection.len() > MAX_COPY_LENGTH =>
+            {
+                format!(
+                    "The selection is too large to copy (at most {} bytes)",
+                    MAX_COPY_LENGTH
+                )
+            }
+            SelectionAction::CopyHex | SelectionAction::CopyCArray => {
+                let mut data = Vec::with_capacity(selection.len() as usize);
+                let result = self.read_selection(&selection, |chunk| {
+                    data.extend_from_slice(chunk);
+                    Ok(())
+                });
+                match result {
+                    Ok(()) => {
+                        self.settings.clipboard = Some(match action {
+                            SelectionAction::CopyHex => format_hex(&data),
+                            _ => format_c_array(&data),
+                        });
+                        format!("Copied {} bytes", data.len())
+                    }
+                    Err(e) => format!("Could not read selection: {}", e),
+                }
+            }
+        };
+    }
+
+    /// Pass the selected data to `f`, in chunks. The selection is clipped to the end of the
+    /// buffer.
+    fn read_selection(
+        &mut self,
+        selection: &Selection,
+        mut f: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        const CHUNK_SIZE: u64 = 1 << 20;
+
+        let length = self.buffer.len();
+        for range in selection.ranges() {
+            let mut start = range.start.min(length);
+            let end = range.end.min(length);
+            while start < end {
+                let chunk_length = (end - start).min(CHUNK_SIZE);
+                self.buffer.prepare(start, chunk_length)?;
+                let data_start = (start - self.buffer.data_offset()) as usize;
+                f(&self.buffer.data()[data_start..(data_start + chunk_length as usize)])?;
+                start += chunk_length;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rank the pixel formats by how well they fit the selected data, if requested.
+    pub fn update_format_guesses(&mut self) {
+        if !std::mem::take(&mut self.settings.format_detection_requested) {
+            return;
+        }
+        let selection = match &self.settings.selection {
+            Some(selection) => selection.truncated(MAX_FORMAT_SAMPLE),
+            None => return,
+        };
+
+        let mut data = Vec::with_capacity(selection.len() as usize);
+        let result = self.read_selection(&selection, |chunk| {
+            data.extend_from_slice(chunk);
+            Ok(())
+        });
+        match result {
+            Ok(()) => self.settings.format_guesses = rank_formats(&data),
+            Err(e) => {
+                self.settings.format_guesses.clear();
+                self.settings.selection_status = format!("Could not read selection: {}", e);
+            }
+        }
+    }
+
+    /// Look for periods in the visible data, if requested, and offer them as widths. Periods
+    /// are measured in bytes and converted to widths in units of the view, taking the stride
+    /// into account.
+    pub fn update_auto_width(&mut self) {
+        if !std::mem::take(&mut self.settings.auto_width_requested) {
+            return;
+        }
+
+        let settings = &self.settings;
+        let start = settings.view_byte_offset();
+        let length = settings
+            .visible_length
+            .min(settings.buffer_length.saturating_sub(start))
+            .min(MAX_AUTO_WIDTH_SAMPLE);
+        let stride = settings.stride as u64;
+        let units_per_byte = settings.units_per_byte();
+        let max_period = settings.max_width() as u64 * stride / units_per_byte;
+
+        self.prepare_buffer(start, length);
+        let data_start = start.saturating_sub(self.buffer.data_offset()) as usize;
+        let data = self.buffer.data();
+        let sample = data
+            .get(data_start..(data_start + length as usize).min(data.len()))
+            .unwrap_or_default();
+
+        self.settings.width_candidates = candidate_periods(sample, max_period as usize, 32)
+            .into_iter()
+            .filter(|period| period.length * units_per_byte % stride == 0)
+            .map(|period| Period {
+                length: period.length * units_per_byte / stride,
+                ..period
+            })
+            .take(8)
+            .collect();
+    }
+
+    /// Decode the data at the position under the mouse cursor.
+    pub fn update_inspector(&mut self) {
+        let position = match self.settings.hovered_position {
+            Some(position) => position,
+            None => {
+                self.settings.inspection = None;
+                return;
+            }
+        };
+
+        let units_per_byte = self.settings.units_per_byte();
+        let start = position / units_per_byte;
+        self.prepare_buffer(start, 8);
+
+        let data = self.buffer.data();
+        let data_start = start.saturating_sub(self.buffer.data_offset()) as usize;
+        let bytes = data
+            .get(data_start..(data_start + 8).min(data.len()))
+            .unwrap_or_default()
+            .to_vec();
+
+        self.settings.inspection = Some(Inspection {
+            offset: self.settings.base_offset + start,
+            bit: self.settings.bit_order.map(|_| position % units_per_byte),
+            bytes,
+        });
     }
 
     pub fn update_hex_view(&mut self) {
         if !self.settings.hex_view_visible {
+            self.hex_view_state = None;
             return;
         }
+        let state = self.current_state();
+        if self.hex_view_state.as_ref() == Some(&state) {
+            return;
+        }
+        self.hex_view_state = Some(state);
 
         let mut hex_view = String::new();
         let mut hex_ascii = String::new();
 
-        let view = View::new(
-            self.buffer.data(),
-            self.settings.offset + self.settings.offset_fine,
-            1,
-        );
-
         let max_size = 4 * 8;
-        let width = (self.settings.width * self.settings.stride).min(max_size);
+        let width =
+            (self.settings.row_length() / self.settings.units_per_byte()).clamp(1, max_size);
         let height = 24;
 
+        let start = self.settings.view_byte_offset();
+        self.prepare_buffer(start, width * height);
+        let view = View::new(self.buffer.data(), self.buffer.data_offset(), start, 1);
+
         for i in 0..(width * height) {
             if i % width == 0 {
                 if i > 0 {
                     hex_view.push('\n');
                     hex_ascii.push('\n');
                 }
-                hex_view.push_str(&format!("{:08x}: ", view.data_index(i)));
+                hex_view.push_str(&format!(
+                    "{:08x}: ",
+                    self.settings.base_offset + view.data_index(i)
+                ));
             } else if i > 0 && (i % width) % 8 == 0 {
                 hex_view.push(' ');
             }
@@ -77,64 +656,304 @@ impl Binocle {
         self.settings.hex_ascii = hex_ascii;
     }
 
-    pub fn draw(&self, frame: &mut [u8]) {
+    /// Make sure that the given range of the buffer is accessible.
+    fn prepare_buffer(&mut self, start: u64, length: u64) {
+        if let Err(e) = self.buffer.prepare(start, length) {
+            error!("Failed to map the file contents: {}", e);
+        }
+    }
+
+    /// Draw the canvas into the top left corner of the frame. The canvas size is given by the
+    /// settings; the rest of the frame is cleared.
+    pub fn draw(&mut self, frame: &mut [u8], frame_width: usize) {
+        self.drawn = Some(self.current_state());
+
+        let canvas_width = (self.settings.canvas_width.max(0) as usize).min(frame_width);
+        let canvas_height =
+            (self.settings.canvas_height.max(0) as usize).min(frame.len() / 4 / frame_width);
+        let rows = canvas_height as u64;
+        let zoom_factor = self.settings.zoom_factor() as u64;
+        let grid_width = self.settings.width as u64;
+        let grid_height = (rows + zoom_factor - 1) / zoom_factor;
+
+        // Multi-byte styles and the entropy window read a bit beyond the last pixel
+        let lookahead = 64.max(self.settings.entropy_window_size as u64);
+        let capacity = self.settings.layout.capacity(grid_width, grid_height);
+        let units_per_byte = self.settings.units_per_byte();
+        self.prepare_buffer(
+            self.settings.view_byte_offset(),
+            capacity * self.settings.stride as u64 / units_per_byte + lookahead,
+        );
+
+        let data = self.buffer.data();
+        let start = self
+            .settings
+            .view_byte_offset()
+            .saturating_sub(self.buffer.data_offset())
+            .min(data.len() as u64);
+        let length = capacity * self.settings.stride as u64 / units_per_byte;
+        let end = (start + length).min(data.len() as u64);
+        let visible = &data[start as usize..end as usize];
+        self.settings.visible_length = length;
+
+        // The histogram and the plots show the selection instead of the visible range, if there
+        // is one (as far as it is accessible)
+        let data_offset = self.buffer.data_offset();
+        let selected: Option<Vec<u8>> = self.settings.selection.as_ref().map(|selection| {
+            let accessible = data_offset..(data_offset + data.len() as u64);
+            selection
+                .ranges()
+                .into_iter()
+                .flat_map(|range| {
+                    let start = range.start.clamp(accessible.start, accessible.end);
+                    let end = range.end.clamp(accessible.start, accessible.end);
+                    &data[(start - data_offset) as usize..(end - data_offset) as usize]
+                })
+                .copied()
+                .collect()
+        });
+        let visible = selected.as_deref().unwrap_or(visible);
+
+        self.settings.histogram.fill(0);
+        for &byte in visible {
+            self.settings.histogram[byte as usize] += 1;
+        }
+
         let settings = &self.settings;
 
+        if settings.canvas_mode != CanvasMode::Grid {
+            let size = (canvas_width, canvas_height);
+            match settings.canvas_mode {
+                CanvasMode::Digraph => Digraph::from_bytes(visible).draw(frame, frame_width, size),
+                CanvasMode::Trigram => Trigram::from_bytes(visible).draw(
+                    frame,
+                    frame_width,
+                    size,
+                    settings.trigram_rotation,
+                ),
+                CanvasMode::Grid => unreachable!(),
+            }
+            return;
+        }
+
         let view = View::new(
             self.buffer.data(),
-            settings.offset + settings.offset_fine,
-            settings.stride,
+            self.buffer.data_offset(),
+            settings.view_offset(),
+            settings.stride as u64,
         );
 
-        let mut style: Box<dyn Style> = match settings.pixel_style {
-            PixelStyle::Colorful => Box::new(Colorful {}),
-            PixelStyle::Grayscale => Box::new(Grayscale {}),
-            PixelStyle::Category => Box::new(Category {}),
-            PixelStyle::GradientMagma => Box::new(ColorGradient::new(colorgrad::magma())),
-            PixelStyle::GradientPlasma => Box::new(ColorGradient::new(colorgrad::plasma())),
-            PixelStyle::GradientViridis => Box::new(ColorGradient::new(colorgrad::viridis())),
-            PixelStyle::GradientRainbow => Box::new(ColorGradient::new(colorgrad::rainbow())),
-            PixelStyle::GradientTurbo => Box::new(ColorGradient::new(colorgrad::turbo())),
-            PixelStyle::GradientCubehelix => {
-                Box::new(ColorGradient::new(colorgrad::cubehelix_default()))
-            }
-            PixelStyle::Rgba => Box::new(Rgba {}),
-            PixelStyle::Abgr => Box::new(Abgr {}),
-            PixelStyle::Rgb => Box::new(Rgb {}),
-            PixelStyle::Bgr => Box::new(Bgr {}),
-            PixelStyle::Entropy => Box::new(Entropy::with_window_size(32)),
-            PixelStyle::Datatype => Box::new(DatatypeStyle::new(
-                match (
-                    &settings.datatype_settings.datatype,
-                    settings.datatype_settings.signedness,
-                ) {
-                    (GuiDatatype::Integer8, signedness) => Datatype::Integer8(signedness),
-                    (GuiDatatype::Integer16, signedness) => Datatype::Integer16(signedness),
-                    (GuiDatatype::Integer32, signedness) => Datatype::Integer32(signedness),
-                    (GuiDatatype::Integer64, signedness) => Datatype::Integer64(signedness),
-                    (GuiDatatype::Float32, _) => Datatype::Float32,
-                    (GuiDatatype::Float64, _) => Datatype::Float64,
-                },
-                settings.datatype_settings.endianness,
-                settings.value_range,
-            )),
+        let mut style: Box<dyn Style> = if let Some(order) = settings.bit_order {
+            Box::new(Bits { order })
+        } else {
+            match settings.pixel_style {
+                PixelStyle::Colorful => Box::new(Colorful {}),
+                PixelStyle::Grayscale => Box::new(Grayscale {}),
+                PixelStyle::Category => Box::new(Category {}),
+                PixelStyle::GradientMagma => Box::new(ColorGradient::new(colorgrad::magma())),
+                PixelStyle::GradientPlasma => Box::new(ColorGradient::new(colorgrad::plasma())),
+                PixelStyle::GradientViridis => Box::new(ColorGradient::new(colorgrad::viridis())),
+                PixelStyle::GradientRainbow => Box::new(ColorGradient::new(colorgrad::rainbow())),
+                PixelStyle::GradientTurbo => Box::new(ColorGradient::new(colorgrad::turbo())),
+                PixelStyle::GradientCubehelix => {
+                    Box::new(ColorGradient::new(colorgrad::cubehelix_default()))
+                }
+                PixelStyle::Rgba => Box::new(Rgba {}),
+                PixelStyle::Abgr => Box::new(Abgr {}),
+                PixelStyle::Rgb => Box::new(Rgb {}),
+                PixelStyle::Bgr => Box::new(Bgr {}),
+                PixelStyle::Rgb565 => Box::new(Rgb565 {}),
+                PixelStyle::Entropy => {
+                    Box::new(Entropy::with_window_size(settings.entropy_window_size))
+                }
+                PixelStyle::Datatype => Box::new(DatatypeStyle::new(
+                    match (
+                        &settings.datatype_settings.datatype,
+                        settings.datatype_settings.signedness,
+                    ) {
+                        (GuiDatatype::Integer8, signedness) => Datatype::Integer8(signedness),
+                        (GuiDatatype::Integer16, signedness) => Datatype::Integer16(signedness),
+                        (GuiDatatype::Integer32, signedness) => Datatype::Integer32(signedness),
+                        (GuiDatatype::Integer64, signedness) => Datatype::Integer64(signedness),
+                        (GuiDatatype::Float32, _) => Datatype::Float32,
+                        (GuiDatatype::Float64, _) => Datatype::Float64,
+                    },
+                    settings.datatype_settings.endianness,
+                    settings.value_range,
+                )),
+            }
         };
         style.init(&view);
+        let style = &*style;
 
-        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            let zoom_factor = settings.zoom_factor();
-            let x = ((i as isize) % WIDTH as isize) / zoom_factor;
-            let y = ((i as isize) / WIDTH as isize) / zoom_factor;
+        let changed = &self.changed;
+        let selection = settings.selection.as_ref();
+        let matches = &settings.search_matches;
+        let visible_start = settings.base_offset + settings.view_byte_offset();
+        let visible_end = visible_start + length;
+        let annotations: Vec<&Annotation> = settings
+            .annotations
+            .iter()
+            .filter(|a| {
+                a.start < visible_end && a.start.saturating_add(a.length.max(1)) > visible_start
+            })
+            .collect();
+        let base_offset = settings.base_offset;
 
-            let color = if x >= settings.width {
-                [0, 0, 0, 0]
-            } else {
-                let view_index = y * settings.width + x;
+        // Rows are rendered in parallel, with one scratch state per thread
+        frame
+            .par_chunks_exact_mut(frame_width * 4)
+            .enumerate()
+            .for_each_init(Scratch::default, |scratch, (row, pixels)| {
+                let y = row as u64 / zoom_factor;
+                for (column, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+                    let x = column as u64 / zoom_factor;
+                    let view_index = if column < canvas_width && row < canvas_height {
+                        settings.layout.view_index(x, y, grid_width, grid_height)
+                    } else {
+                        None
+                    };
 
-                style.color_at_index(&view, view_index)
-            };
+                    let color = if let Some(view_index) = view_index {
+                        let color = style.color_at_index(&view, view_index, scratch);
+                        let byte_index = view.data_index(view_index) / units_per_byte;
+                        let color = if settings.highlight_changes
+                            && !changed.is_empty()
+                            && is_changed(changed, byte_index)
+                        {
+                            highlight(color)
+                        } else if let Some(byte) = settings.highlighted_byte {
+                            let matches = byte_index
+                                .checked_sub(data_offset)
+                                .and_then(|index| data.get(index as usize))
+                                == Some(&byte);
+                            if matches {
+                                highlight(color)
+                            } else {
+                                dim(color)
+                            }
+                        } else {
+                            color
+                        };
 
-            pixel.copy_from_slice(&color);
-        }
+                        let color = if in_ranges(matches, byte_index) {
+                            mark(color)
+                        } else {
+                            color
+                        };
+
+                        // Later annotations are drawn on top of earlier ones
+                        let offset = base_offset + byte_index;
+                        let color = match annotations.iter().rev().find(|a| a.contains(offset)) {
+                            Some(annotation) => blend(color, annotation.color),
+                            None => color,
+                        };
+
+                        match selection {
+                            Some(selection) if selection.contains(byte_index) => tint(color),
+                            _ => color,
+                        }
+                    } else {
+                        [0, 0, 0, 0]
+                    };
+
+                    pixel.copy_from_slice(&color);
+                }
+            });
+    }
+}
+
+fn open_file(
+    filename: &str,
+    backing: BackingOption,
+    compression: Option<Compression>,
+    window: Window,
+) -> io::Result<Buffer> {
+    match (compression, backing) {
+        (Some(compression), _) => decompress(filename, compression, window),
+        (None, BackingOption::File) => Buffer::from_file(filename, window),
+        (None, BackingOption::Mmap) => Buffer::from_mmap(filename, window),
+        (None, BackingOption::Paged) => Buffer::from_paged(filename, window),
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether the byte at the given index lies within one of the (sorted) changed ranges.
+fn is_changed(changed: &[Range<usize>], data_index: u64) -> bool {
+    let data_index = data_index as usize;
+    let i = changed.partition_point(|range| range.end <= data_index);
+    changed
+        .get(i)
+        .map_or(false, |range| range.contains(&data_index))
+}
+
+fn highlight(color: Color) -> Color {
+    let [r, g, b, _] = color;
+    [r / 3 + 170, g / 3 + 170, b / 3, 255]
+}
+
+/// Whether the position lies within one of the (sorted) ranges.
+fn in_ranges(ranges: &[Range<u64>], position: u64) -> bool {
+    let i = ranges.partition_point(|range| range.end <= position);
+    ranges
+        .get(i)
+        .map_or(false, |range| range.contains(&position))
+}
+
+/// Mark search matches by shifting them towards green.
+fn mark(color: Color) -> Color {
+    let [r, g, b, _] = color;
+    [r / 2, g / 2 + 127, b / 2, 255]
+}
+
+/// Mix the color half and half with the color of an annotation.
+fn blend(color: Color, [ar, ag, ab]: [u8; 3]) -> Color {
+    let [r, g, b, _] = color;
+    [r / 2 + ar / 2, g / 2 + ag / 2, b / 2 + ab / 2, 255]
+}
+
+/// Mark selected pixels by shifting them towards blue.
+fn tint(color: Color) -> Color {
+    let [r, g, b, _] = color;
+    [r / 2, g / 2 + 40, b / 2 + 127, 255]
+}
+
+fn dim(color: Color) -> Color {
+    let [r, g, b, a] = color;
+    [r / 4, g / 4, b / 4, a]
+}
+
+#[test]
+fn files_that_only_look_compressed_are_shown_raw() {
+    let contents: [&[u8]; 2] = [
+        b"BZh is a plain text file",
+        b"BZh91AY&SY, but no bzip2 data",
+    ];
+    for content in contents {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(content).unwrap();
+        let path = file.path().to_str().unwrap();
+        let arguments = [
+            "binocle",
+            path,
+            "--backing",
+            "file",
+            "--output",
+            "unused.png",
+        ];
+
+        let binocle = Binocle::new(CliOptions::try_parse_from(arguments).unwrap()).unwrap();
+        assert_eq!(binocle.buffer.data(), content);
+        assert_eq!(binocle.settings.compression, None);
+        assert_eq!(
+            binocle.settings.decompression_error.is_some(),
+            content.starts_with(b"BZh9")
+        );
     }
 }
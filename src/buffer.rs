@@ -1,52 +1,715 @@
-use memmap2::Mmap;
-use std::io::{BufReader, Read};
+use memmap2::{Mmap, MmapOptions};
+
+use crate::process::{ProcessMemory, PAGE_SIZE};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
 use std::{fs::File, io, path::Path};
 
+/// Files larger than this are not mapped as a whole, but through a `PagedBacking`.
+const FULL_MAP_LIMIT: u64 = (isize::MAX as u64) / 2;
+
+/// The part of the input that is loaded into the buffer.
+#[derive(Clone, Copy, Default)]
+pub struct Window {
+    pub offset: u64,
+    pub length: Option<u64>,
+}
+
+impl Window {
+    /// Skip the bytes in front of the window and limit the reader to the window length.
+    pub fn apply<R: Read>(&self, mut reader: R) -> io::Result<io::Take<R>> {
+        io::copy(&mut reader.by_ref().take(self.offset), &mut io::sink())?;
+        Ok(reader.take(self.length.unwrap_or(u64::MAX)))
+    }
+}
+
 pub struct MMapBacking {
     _file: File,
-    pub mmap: Mmap,
+    pub mmap: Arc<Mmap>,
 }
 
 impl MMapBacking {
     pub fn new(_file: File, mmap: Mmap) -> Self {
-        MMapBacking { _file, mmap }
+        MMapBacking {
+            _file,
+            mmap: Arc::new(mmap),
+        }
     }
 }
 
+/// Maps a fixed-size part of a (potentially huge) file or block device at a time, around the
+/// range that is currently being viewed.
+pub struct PagedBacking {
+    file: File,
+    /// Position of the start of the buffer within the file
+    offset: u64,
+    length: u64,
+    /// Position of the start of the current mapping within the buffer
+    mapped_start: u64,
+    mmap: Option<Mmap>,
+}
+
+impl PagedBacking {
+    const MAPPING_SIZE: u64 = 256 * 1024 * 1024;
+    const ALIGNMENT: u64 = 64 * 1024;
+
+    pub fn new(file: File, offset: u64, length: u64) -> Self {
+        PagedBacking {
+            file,
+            offset,
+            length,
+            mapped_start: 0,
+            mmap: None,
+        }
+    }
+
+    fn mapped(&self) -> &[u8] {
+        self.mmap.as_deref().unwrap_or(&[])
+    }
+
+    /// Make sure that the given range is mapped, moving the mapping if necessary.
+    pub fn prepare(&mut self, start: u64, length: u64) -> io::Result<()> {
+        let end = start.saturating_add(length).min(self.length);
+        let start = start.min(end);
+
+        let mapped_end = self.mapped_start + self.mapped().len() as u64;
+        if self.mmap.is_some() && start >= self.mapped_start && end <= mapped_end {
+            return Ok(());
+        }
+
+        // Leave some room in front of the requested range such that scrolling back does not
+        // immediately require a new mapping.
+        let map_start =
+            start.saturating_sub(Self::MAPPING_SIZE / 4) / Self::ALIGNMENT * Self::ALIGNMENT;
+        let map_end = end.max(map_start + Self::MAPPING_SIZE).min(self.length);
+
+        self.mmap = None;
+        self.mapped_start = map_start;
+        if map_end > map_start {
+            self.mmap = Some(unsafe {
+                MmapOptions::new()
+                    .offset(self.offset + map_start)
+                    .len((map_end - map_start) as usize)
+                    .map(&self.file)?
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Data that is read incrementally from a pipe by a background thread. It is kept in the chunks
+/// in which it arrives, such that snapshots can share them. The range that is currently being
+/// viewed is copied into a contiguous window.
+pub struct StreamBacking {
+    chunks: Vec<Arc<Vec<u8>>>,
+    /// Position of the start of each chunk within the buffer
+    chunk_starts: Vec<u64>,
+    length: u64,
+    /// Position of the start of `window` within the buffer
+    window_start: u64,
+    window: Vec<u8>,
+    receiver: Option<Receiver<io::Result<Vec<u8>>>>,
+}
+
+impl StreamBacking {
+    const CHUNK_SIZE: usize = 64 * 1024;
+    /// Small reads are appended to the last chunk up to this size (unless a snapshot shares it)
+    const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+    /// The most that is copied into the window at a time. Only half of it is guaranteed to be
+    /// accessible after `prepare`.
+    const MAX_WINDOW: u64 = 64 * 1024 * 1024;
+    const MIN_WINDOW: u64 = 64 * 1024;
+
+    pub fn new<R: Read + Send + 'static>(reader: R, window: Window) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || match window.apply(reader) {
+            Ok(mut reader) => Self::forward(&mut reader, &sender),
+            Err(e) => {
+                let _ = sender.send(Err(e));
+            }
+        });
+
+        StreamBacking {
+            chunks: vec![],
+            chunk_starts: vec![],
+            length: 0,
+            window_start: 0,
+            window: vec![],
+            receiver: Some(receiver),
+        }
+    }
+
+    /// The data that has arrived so far, sharing the chunks with this stream.
+    fn snapshot(&self) -> Self {
+        StreamBacking {
+            chunks: self.chunks.clone(),
+            chunk_starts: self.chunk_starts.clone(),
+            length: self.length,
+            window_start: 0,
+            window: vec![],
+            receiver: None,
+        }
+    }
+
+    /// Send chunks of data to the receiving end until the reader is exhausted.
+    fn forward<R: Read>(reader: &mut R, sender: &mpsc::Sender<io::Result<Vec<u8>>>) {
+        loop {
+            let mut chunk = vec![0; Self::CHUNK_SIZE];
+            let result = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    chunk.truncate(n);
+                    Ok(chunk)
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        }
+    }
+
+    /// Whether the stream has ended (or failed), such that no more data will arrive.
+    pub fn is_finished(&self) -> bool {
+        self.receiver.is_none()
+    }
+
+    /// Append all chunks that have arrived so far. Returns `true` if new data was added.
+    pub fn poll(&mut self, block: bool) -> io::Result<bool> {
+        let mut changed = false;
+
+        while let Some(receiver) = &self.receiver {
+            let chunk = if block {
+                receiver.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                receiver.try_recv()
+            };
+
+            match chunk {
+                Ok(chunk) => {
+                    self.append(chunk?);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.receiver = None,
+            }
+        }
+
+        Ok(changed)
+    }
+
+    fn append(&mut self, chunk: Vec<u8>) {
+        if let Some(last) = self.chunks.last_mut().and_then(Arc::get_mut) {
+            if last.len() + chunk.len() <= Self::MAX_CHUNK_SIZE {
+                last.extend_from_slice(&chunk);
+                self.length += chunk.len() as u64;
+                return;
+            }
+        }
+        self.chunk_starts.push(self.length);
+        self.length += chunk.len() as u64;
+        self.chunks.push(Arc::new(chunk));
+    }
+
+    /// Make sure that the given range is copied into the window, moving it if necessary. Ranges
+    /// longer than half of `MAX_WINDOW` are only partially accessible.
+    pub fn prepare(&mut self, start: u64, length: u64) {
+        let end = start
+            .saturating_add(length.min(Self::MAX_WINDOW / 2))
+            .min(self.length);
+        let start = start.min(end);
+
+        let window_end = self.window_start + self.window.len() as u64;
+        if start >= self.window_start && end <= window_end {
+            return;
+        }
+
+        let window_length = ((end - start) * 2).clamp(Self::MIN_WINDOW, Self::MAX_WINDOW);
+        self.window_start = start.saturating_sub(window_length / 4);
+        let window_end = (self.window_start + window_length).min(self.length);
+
+        self.window.clear();
+        let mut position = self.window_start;
+        let mut index = self
+            .chunk_starts
+            .partition_point(|&start| start <= position);
+        while position < window_end {
+            // The chunk that contains the position
+            let chunk = &self.chunks[index - 1];
+            let offset = (position - self.chunk_starts[index - 1]) as usize;
+            let length = (chunk.len() - offset).min((window_end - position) as usize);
+            self.window
+                .extend_from_slice(&chunk[offset..(offset + length)]);
+            position += length as u64;
+            index += 1;
+        }
+    }
+}
+
+/// The memory of a running process, one mapping at a time. Mappings can be huge (and mostly
+/// unbacked), so only a window around the range that is currently being viewed is read.
+pub struct ProcessBacking {
+    pub memory: ProcessMemory,
+    pub selected: Option<usize>,
+    /// Position of the start of `data` within the selected mapping
+    data_start: u64,
+    pub data: Vec<u8>,
+}
+
+impl ProcessBacking {
+    /// The most that is read at a time. Only half of it is guaranteed to be accessible after
+    /// `prepare`, the rest is read ahead and behind.
+    const MAX_READ: u64 = 64 * 1024 * 1024;
+    /// Small ranges are read together with their surroundings, such that scrolling a bit does
+    /// not require a new read
+    const MIN_READ: u64 = 64 * 1024;
+
+    pub fn new(memory: ProcessMemory) -> Self {
+        let mut process = ProcessBacking {
+            memory,
+            selected: None,
+            data_start: 0,
+            data: vec![],
+        };
+        if let Some(index) = process.memory.default_mapping() {
+            process.select(index);
+        }
+        process
+    }
+
+    /// Switch to another mapping. Nothing is read until the first call to `prepare`.
+    pub fn select(&mut self, index: usize) {
+        self.selected = Some(index);
+        self.data_start = 0;
+        self.data = vec![];
+    }
+
+    /// The length of the selected mapping.
+    pub fn length(&self) -> u64 {
+        self.selected
+            .map_or(0, |index| self.memory.mappings[index].len())
+    }
+
+    /// Make sure that the given range is read, moving the window if necessary. Ranges longer
+    /// than half of `MAX_READ` are only partially accessible.
+    pub fn prepare(&mut self, start: u64, length: u64) {
+        let index = match self.selected {
+            Some(index) => index,
+            None => return,
+        };
+        let end = start
+            .saturating_add(length.min(Self::MAX_READ / 2))
+            .min(self.length());
+        let start = start.min(end);
+
+        let data_end = self.data_start + self.data.len() as u64;
+        if start >= self.data_start && end <= data_end {
+            return;
+        }
+
+        let read_length = ((end - start) * 2).clamp(Self::MIN_READ, Self::MAX_READ);
+        let window_start = start.saturating_sub(read_length / 4) / PAGE_SIZE * PAGE_SIZE;
+        let window_end = (window_start + read_length).min(self.length());
+
+        self.data_start = window_start;
+        self.data = vec![0; (window_end - window_start) as usize];
+        self.memory.read(index, window_start, &mut self.data);
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 pub enum Buffer {
-    VecBuffer(Vec<u8>),
+    /// Data that is held in memory. It is shared with snapshots, so they do not copy it
+    VecBuffer(Arc<Vec<u8>>),
     MmapBuffer(MMapBacking),
+    StreamBuffer(StreamBacking),
+    ProcessBuffer(ProcessBacking),
+    PagedBuffer(PagedBacking),
 }
 
 impl Buffer {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    pub fn from_file<P: AsRef<Path>>(path: P, window: Window) -> io::Result<Self> {
         let mut data: Vec<u8> = vec![];
 
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
+        let mut file = File::open(path)?;
+        let length = window_length(&file, window)?;
+        file.seek(SeekFrom::Start(window.offset))?;
+        let mut reader = BufReader::new(file).take(length);
 
         reader.read_to_end(&mut data)?;
 
-        Ok(Buffer::VecBuffer(data))
+        Ok(Buffer::VecBuffer(Arc::new(data)))
     }
 
-    pub fn from_mmap<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    /// Map the whole window into memory. Block devices and files that are too large for the
+    /// address space fall back to a `PagedBuffer`.
+    pub fn from_mmap<P: AsRef<Path>>(path: P, window: Window) -> io::Result<Self> {
         let file = File::open(path)?;
-        let mmap = unsafe { Mmap::map(&file)? };
+        let length = window_length(&file, window)?;
+
+        if length > FULL_MAP_LIMIT || is_block_device(&file)? {
+            let paged = PagedBacking::new(file, window.offset, length);
+            return Ok(Buffer::PagedBuffer(paged));
+        }
+
+        let mmap = unsafe {
+            MmapOptions::new()
+                .offset(window.offset)
+                .len(length as usize)
+                .map(&file)?
+        };
         Ok(Buffer::MmapBuffer(MMapBacking::new(file, mmap)))
     }
 
-    pub fn len(&self) -> usize {
+    pub fn from_paged<P: AsRef<Path>>(path: P, window: Window) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let length = window_length(&file, window)?;
+        let paged = PagedBacking::new(file, window.offset, length);
+        Ok(Buffer::PagedBuffer(paged))
+    }
+
+    pub fn from_stream<R: Read + Send + 'static>(reader: R, window: Window) -> Self {
+        Buffer::StreamBuffer(StreamBacking::new(reader, window))
+    }
+
+    pub fn from_process(pid: u32) -> io::Result<Self> {
+        let memory = ProcessMemory::open(pid)?;
+        Ok(Buffer::ProcessBuffer(ProcessBacking::new(memory)))
+    }
+
+    /// Pick up data that has arrived since the last call (for streamed input). Returns `true`
+    /// if the buffer has grown. If `block` is set, wait until the stream is exhausted.
+    pub fn poll(&mut self, block: bool) -> io::Result<bool> {
+        match self {
+            Buffer::StreamBuffer(stream) => stream.poll(block),
+            _ => Ok(false),
+        }
+    }
+
+    /// Whether more data can arrive through `poll`.
+    pub fn is_receiving(&self) -> bool {
+        match self {
+            Buffer::StreamBuffer(stream) => !stream.is_finished(),
+            _ => false,
+        }
+    }
+
+    /// An independent copy of the buffer that can be handed to another thread. In-memory data
+    /// (including the data of streams that has arrived so far) and mappings are shared, files
+    /// and process memory are opened again (or mapped again).
+    pub fn snapshot(&self) -> io::Result<Buffer> {
+        Ok(match self {
+            Buffer::VecBuffer(data) => Buffer::VecBuffer(Arc::clone(data)),
+            Buffer::MmapBuffer(mmap) => Buffer::MmapBuffer(MMapBacking {
+                _file: mmap._file.try_clone()?,
+                mmap: Arc::clone(&mmap.mmap),
+            }),
+            Buffer::StreamBuffer(stream) => Buffer::StreamBuffer(stream.snapshot()),
+            Buffer::ProcessBuffer(process) => Buffer::ProcessBuffer(ProcessBacking {
+                memory: process.memory.try_clone()?,
+                selected: process.selected,
+                data_start: 0,
+                data: vec![],
+            }),
+            Buffer::PagedBuffer(paged) => Buffer::PagedBuffer(PagedBacking::new(
+                paged.file.try_clone()?,
+                paged.offset,
+                paged.length,
+            )),
+        })
+    }
+
+    /// Make sure that the given range is accessible through `data()`. This only has an effect
+    /// for a `PagedBuffer`, a `StreamBuffer` and a `ProcessBuffer`.
+    pub fn prepare(&mut self, start: u64, length: u64) -> io::Result<()> {
         match self {
-            Buffer::VecBuffer(data) => data.len(),
-            Buffer::MmapBuffer(mmap) => mmap.mmap.len(),
+            Buffer::PagedBuffer(paged) => paged.prepare(start, length),
+            Buffer::StreamBuffer(stream) => {
+                stream.prepare(start, length);
+                Ok(())
+            }
+            Buffer::ProcessBuffer(process) => {
+                process.prepare(start, length);
+                Ok(())
+            }
+            _ => Ok(()),
         }
     }
 
+    pub fn len(&self) -> u64 {
+        match self {
+            Buffer::PagedBuffer(paged) => paged.length,
+            Buffer::StreamBuffer(stream) => stream.length,
+            Buffer::ProcessBuffer(process) => process.length(),
+            _ => self.data().len() as u64,
+        }
+    }
+
+    /// The data that is currently accessible. For a `PagedBuffer` (and a `StreamBuffer` or a
+    /// `ProcessBuffer`), this is only the mapped (or copied) part of the buffer, starting at
+    /// `data_offset()`.
     pub fn data(&self) -> &[u8] {
         match self {
             Buffer::VecBuffer(data) => data,
             Buffer::MmapBuffer(mmap) => &mmap.mmap,
+            Buffer::StreamBuffer(stream) => &stream.window,
+            Buffer::ProcessBuffer(process) => &process.data,
+            Buffer::PagedBuffer(paged) => paged.mapped(),
+        }
+    }
+
+    /// Position of the start of `data()` within the buffer.
+    pub fn data_offset(&self) -> u64 {
+        match self {
+            Buffer::PagedBuffer(paged) => paged.mapped_start,
+            Buffer::StreamBuffer(stream) => stream.window_start,
+            Buffer::ProcessBuffer(process) => process.data_start,
+            _ => 0,
         }
     }
 }
+
+/// The length of the part of the file that is covered by the window.
+fn window_length(file: &File, window: Window) -> io::Result<u64> {
+    let file_length = file_length(file)?;
+    if window.offset > file_length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "offset is beyond the end of the file",
+        ));
+    }
+    Ok(window
+        .length
+        .unwrap_or(u64::MAX)
+        .min(file_length - window.offset))
+}
+
+fn file_length(file: &File) -> io::Result<u64> {
+    if is_block_device(file)? {
+        block_device_size(file)
+    } else {
+        Ok(file.metadata()?.len())
+    }
+}
+
+fn is_block_device(file: &File) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        Ok(file.metadata()?.file_type().is_block_device())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = file;
+        Ok(false)
+    }
+}
+
+/// Block devices report a size of zero in their metadata, so we have to ask the kernel.
+#[cfg(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )
+))]
+fn block_device_size(file: &File) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    // _IOR(0x12, 114, size_t), see linux/fs.h
+    const BLKGETSIZE64: u64 =
+        (2 << 30) | ((std::mem::size_of::<usize>() as u64) << 16) | (0x12 << 8) | 114;
+
+    let mut size: u64 = 0;
+    let result = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            BLKGETSIZE64 as libc::Ioctl,
+            &mut size as *mut u64,
+        )
+    };
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(size)
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )
+)))]
+fn block_device_size(mut file: &File) -> io::Result<u64> {
+    file.seek(SeekFrom::End(0))
+}
+
+/// Named pipes can not be mapped into memory or read up front, they have to be streamed.
+pub fn is_named_pipe(path: &str) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if let Ok(metadata) = std::fs::metadata(path) {
+            return metadata.file_type().is_fifo();
+        }
+    }
+
+    false
+}
+
+#[test]
+fn stream_buffer_collects_all_chunks() {
+    let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+    let mut buffer = Buffer::from_stream(io::Cursor::new(data.clone()), Window::default());
+
+    assert!(buffer.is_receiving());
+    assert!(buffer.poll(true).unwrap());
+    assert_eq!(buffer.len(), data.len() as u64);
+    buffer.prepare(0, buffer.len()).unwrap();
+    assert_eq!(buffer.data(), &data[..]);
+    assert!(!buffer.poll(true).unwrap());
+    assert!(!buffer.is_receiving());
+
+    // Snapshots share the chunks, but have a window of their own
+    let mut snapshot = buffer.snapshot().unwrap();
+    assert_eq!(snapshot.len(), buffer.len());
+    let start = 2_500_000;
+    snapshot.prepare(start, 1000).unwrap();
+    let relative = (start - snapshot.data_offset()) as usize;
+    assert_eq!(
+        &snapshot.data()[relative..(relative + 1000)],
+        &data[(start as usize)..(start as usize + 1000)]
+    );
+    assert_eq!(buffer.data_offset(), 0);
+}
+
+#[test]
+fn buffer_window() {
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i % 253) as u8).collect();
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut file, &data).unwrap();
+
+    let window = Window {
+        offset: 5000,
+        length: Some(12345),
+    };
+    let expected = &data[5000..(5000 + 12345)];
+
+    let buffer = Buffer::from_file(file.path(), window).unwrap();
+    assert_eq!(buffer.data(), expected);
+
+    let buffer = Buffer::from_mmap(file.path(), window).unwrap();
+    assert_eq!(buffer.data(), expected);
+
+    let mut buffer = Buffer::from_stream(io::Cursor::new(data.clone()), window);
+    buffer.poll(true).unwrap();
+    buffer.prepare(0, buffer.len()).unwrap();
+    assert_eq!(buffer.data(), expected);
+
+    let open_ended = Window {
+        offset: 99_000,
+        length: None,
+    };
+    let buffer = Buffer::from_mmap(file.path(), open_ended).unwrap();
+    assert_eq!(buffer.data(), &data[99_000..]);
+
+    let beyond_end = Window {
+        offset: 100_001,
+        length: None,
+    };
+    assert!(Buffer::from_file(file.path(), beyond_end).is_err());
+    assert!(Buffer::from_mmap(file.path(), beyond_end).is_err());
+}
+
+#[test]
+fn paged_buffer_moves_mapping() {
+    let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 241) as u8).collect();
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut file, &data).unwrap();
+
+    let window = Window {
+        offset: 1000,
+        length: None,
+    };
+    let mut buffer = Buffer::from_paged(file.path(), window).unwrap();
+    assert_eq!(buffer.len(), 999_000);
+
+    for start in [0, 500_000, 998_000] {
+        buffer.prepare(start, 1000).unwrap();
+        let data_offset = buffer.data_offset();
+        assert!(data_offset <= start);
+
+        let relative = (start - data_offset) as usize;
+        let absolute = (window.offset + start) as usize;
+        assert_eq!(
+            &buffer.data()[relative..(relative + 1000)],
+            &data[absolute..(absolute + 1000)]
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn process_buffer_reads_large_mappings_lazily() {
+    // A reserved, but unbacked mapping that is much larger than a single read
+    const SIZE: usize = 256 << 20;
+    let address = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            SIZE,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+            0,
+        )
+    };
+    if address == libc::MAP_FAILED {
+        // E.g. because of an address space limit, which has nothing to do with what is tested
+        eprintln!("skipped: {}", io::Error::last_os_error());
+        return;
+    }
+
+    let memory = ProcessMemory::open(std::process::id()).unwrap();
+    let (index, mapping) = memory
+        .mappings
+        .iter()
+        .enumerate()
+        .find(|(_, m)| m.start <= address as u64 && m.end >= address as u64 + SIZE as u64)
+        .unwrap();
+    let end = address as u64 + SIZE as u64 - mapping.start;
+    let mut buffer = Buffer::ProcessBuffer(ProcessBacking::new(memory));
+    if let Buffer::ProcessBuffer(process) = &mut buffer {
+        process.select(index);
+    }
+    assert!(buffer.len() >= SIZE as u64);
+    assert!(buffer.data().is_empty());
+
+    // Only the surroundings of the requested range are read, however long it is
+    let start = end - 1000;
+    buffer.prepare(start, 1 << 40).unwrap();
+    assert!(buffer.data().len() as u64 <= ProcessBacking::MAX_READ);
+    assert!(buffer.data_offset() <= start);
+    let relative = (start - buffer.data_offset()) as usize;
+    assert!(buffer.data()[relative..relative + 1000]
+        .iter()
+        .all(|&b| b == 0));
+
+    unsafe { libc::munmap(address, SIZE) };
+}
@@ -1,11 +1,18 @@
 use std::convert::TryInto;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Endianness {
     Big,
     Little,
 }
 
+/// The order in which the bits of a byte are read in bit-level mode.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum Signedness {
     Unsigned,
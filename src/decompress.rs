@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use crate::buffer::{Buffer, MMapBacking, Window};
+
+/// Decompressed data up to this size is kept in memory. Larger outputs are written to a
+/// temporary file which is then memory-mapped.
+const IN_MEMORY_LIMIT: u64 = 512 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+impl Compression {
+    /// Identify the compression format from the magic bytes at the start of the data. Where the
+    /// magic number is short, further header fields are checked as well, such that other files
+    /// are not mistaken for compressed ones.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        // gzip: magic number and compression method 8 (deflate), the only one in use
+        if header.starts_with(&[0x1f, 0x8b, 0x08]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if Self::is_bzip2(header) {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// 'BZh', the block size ('1' to '9') and the magic number of the first block (or of the end
+    /// of the stream, for empty data).
+    fn is_bzip2(header: &[u8]) -> bool {
+        const BLOCK_MAGIC: [u8; 6] = [0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+        const END_MAGIC: [u8; 6] = [0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+
+        match header {
+            [b'B', b'Z', b'h', b'1'..=b'9', magic @ ..] if magic.len() >= 6 => {
+                magic[..6] == BLOCK_MAGIC || magic[..6] == END_MAGIC
+            }
+            _ => false,
+        }
+    }
+
+    pub fn detect_file<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let mut header = Vec::with_capacity(10);
+        File::open(path)?.take(10).read_to_end(&mut header)?;
+        Ok(Self::detect(&header))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+
+    fn decoder<'a, R: Read + 'a>(&self, reader: BufReader<R>) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+            Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+            Compression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+/// Decompress a file into a buffer. The window refers to positions in the decompressed data.
+pub fn decompress<P: AsRef<Path>>(
+    path: P,
+    compression: Compression,
+    window: Window,
+) -> io::Result<Buffer> {
+    let file = File::open(path)?;
+    let mut decoder = window.apply(compression.decoder(BufReader::new(file))?)?;
+
+    let mut data = vec![];
+    decoder
+        .by_ref()
+        .take(IN_MEMORY_LIMIT)
+        .read_to_end(&mut data)?;
+
+    if (data.len() as u64) < IN_MEMORY_LIMIT {
+        return Ok(Buffer::VecBuffer(Arc::new(data)));
+    }
+
+    let mut temp_file = tempfile::tempfile()?;
+    temp_file.write_all(&data)?;
+    drop(data);
+    io::copy(&mut decoder, &mut temp_file)?;
+
+    let mmap = unsafe { Mmap::map(&temp_file)? };
+    Ok(Buffer::MmapBuffer(MMapBacking::new(temp_file, mmap)))
+}
+
+#[test]
+fn detect_compression() {
+    assert_eq!(
+        Compression::detect(&[0x1f, 0x8b, 0x08, 0x00]),
+        Some(Compression::Gzip)
+    );
+    assert_eq!(
+        Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+        Some(Compression::Zstd)
+    );
+    assert_eq!(
+        Compression::detect(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+        Some(Compression::Xz)
+    );
+    assert_eq!(
+        Compression::detect(b"BZh91AY&SY\x00"),
+        Some(Compression::Bzip2)
+    );
+    assert_eq!(Compression::detect(b"\x7fELF"), None);
+    assert_eq!(Compression::detect(&[0x1f]), None);
+    assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x07]), None);
+    assert_eq!(Compression::detect(b"BZh is not bzip2"), None);
+    assert_eq!(Compression::detect(b"BZh0AY&SY"), None);
+}
+
+#[test]
+fn decompress_gzip_file() {
+    use flate2::write::GzEncoder;
+
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    let mut encoder = GzEncoder::new(&mut file, flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(
+        Compression::detect_file(file.path()).unwrap(),
+        Some(Compression::Gzip)
+    );
+    let buffer = decompress(file.path(), Compression::Gzip, Window::default()).unwrap();
+    assert_eq!(buffer.data(), &data[..]);
+}
@@ -1,4 +1,6 @@
+use std::f32::consts::FRAC_PI_2;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use log::error;
@@ -12,41 +14,52 @@ use winit_input_helper::WinitInputHelper;
 use crate::binocle::Binocle;
 use crate::gui::Gui;
 use crate::options::CliOptions;
-use crate::settings::{HEIGHT, WIDTH};
+use crate::settings::{CanvasMode, HEIGHT, WIDTH};
+
+/// How often live input (streams and followed files) is checked for new data
+const LIVE_INPUT_INTERVAL: Duration = Duration::from_millis(100);
+
+const MIN_WIDTH: f64 = 640.0;
+const MIN_HEIGHT: f64 = 480.0;
 
 enum MouseDragAction {
     Nothing,
     ControlOffset {
         start_y: f32,
-        start_offset: isize,
+        start_offset: u64,
     },
     ControlOffsetFine {
         start_x: f32,
-        start_offset_fine: isize,
+        start_offset_fine: u64,
     },
     ControlWidth {
         start_x: f32,
         start_width: isize,
     },
+    ControlRotation {
+        start_x: f32,
+        start_y: f32,
+        start_rotation: (f32, f32),
+    },
+    Select {
+        start_x: f32,
+        start_y: f32,
+        rectangular: bool,
+    },
 }
 
 pub fn run(options: CliOptions) -> Result<()> {
     env_logger::init();
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
+    let pid = options.pid;
+    let mut binocle = Binocle::new(options)?;
     let window = {
         let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
         WindowBuilder::new()
-            .with_title(format!(
-                "binocle - {}",
-                Path::new(&options.filename)
-                    .file_name()
-                    .map(|f| f.to_string_lossy())
-                    .as_deref()
-                    .unwrap_or("<unknown>")
-            ))
+            .with_title(window_title(binocle.filename(), pid))
             .with_inner_size(size)
-            .with_min_inner_size(size)
+            .with_min_inner_size(LogicalSize::new(MIN_WIDTH, MIN_HEIGHT))
             .build(&event_loop)
             .unwrap()
     };
@@ -55,7 +68,9 @@ pub fn run(options: CliOptions) -> Result<()> {
         let window_size = window.inner_size();
         let scale_factor = window.scale_factor();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        let pixels = Pixels::new(WIDTH, HEIGHT, surface_texture)?;
+        // The pixel buffer covers the whole window, such that one pixel of the canvas
+        // corresponds to one physical pixel on screen
+        let pixels = Pixels::new(window_size.width, window_size.height, surface_texture)?;
         let gui = Gui::new(
             &event_loop,
             window_size.width,
@@ -66,24 +81,36 @@ pub fn run(options: CliOptions) -> Result<()> {
         (pixels, gui)
     };
 
-    let mut binocle = Binocle::new(options)?;
+    let mut frame_width = window.inner_size().width as usize;
+    binocle.settings.canvas_height = window.inner_size().height as isize;
 
     let mut mouse_drag_action = MouseDragAction::Nothing;
+    // Whether there were window events (e.g. mouse movement) since the last update. These
+    // might affect the GUI even if the canvas stays the same.
+    let mut window_events = true;
 
     event_loop.run(move |event, _, control_flow| {
         // Update egui inputs
         if let Event::WindowEvent { event, .. } = &event {
             gui.handle_event(event);
+            window_events = true;
         }
 
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            // Draw the binocle
-            binocle.draw(pixels.frame_mut());
+            // Draw the binocle, unless nothing has changed
+            if binocle.needs_redraw() {
+                binocle.draw(pixels.frame_mut(), frame_width);
+            }
 
             // Prepare egui
             gui.prepare(&window, &mut binocle.settings);
 
+            // Settings that have been changed through the GUI are picked up in the next update
+            if binocle.needs_redraw() {
+                *control_flow = ControlFlow::Poll;
+            }
+
             // Render everything together
             let render_result = pixels.render_with(|encoder, render_target, context| {
                 // Render the binocle texture
@@ -107,10 +134,14 @@ pub fn run(options: CliOptions) -> Result<()> {
 
         // Handle input events
         if input.update(&event) {
+            if let Err(e) = binocle.poll_buffer() {
+                error!("Failed to read input: {}", e);
+            }
+
             {
                 let settings = &mut binocle.settings;
 
-                let offset_factor = if input.held_shift() { 1 } else { 160 };
+                let offset_factor: u64 = if input.held_shift() { 1 } else { 160 };
 
                 if !settings.gui_wants_keyboard {
                     // Close events
@@ -153,13 +184,15 @@ pub fn run(options: CliOptions) -> Result<()> {
                     }
 
                     if input.key_pressed(VirtualKeyCode::Up) {
-                        settings.offset -= offset_factor * settings.width * settings.stride;
+                        settings.offset = settings
+                            .offset
+                            .saturating_sub(offset_factor * settings.row_length());
                     } else if input.key_pressed(VirtualKeyCode::Down) {
-                        settings.offset += offset_factor * settings.width * settings.stride;
+                        settings.offset += offset_factor * settings.row_length();
                     }
 
                     if input.key_pressed(VirtualKeyCode::N) {
-                        settings.offset -= 1;
+                        settings.offset = settings.offset.saturating_sub(1);
                     } else if input.key_pressed(VirtualKeyCode::M) {
                         settings.offset += 1;
                     }
@@ -171,9 +204,11 @@ pub fn run(options: CliOptions) -> Result<()> {
                     }
 
                     if input.key_pressed(VirtualKeyCode::PageUp) {
-                        settings.offset -= settings.width * settings.stride * (HEIGHT as isize);
+                        settings.offset = settings
+                            .offset
+                            .saturating_sub(settings.row_length() * settings.grid_height());
                     } else if input.key_pressed(VirtualKeyCode::PageDown) {
-                        settings.offset += settings.width * settings.stride * (HEIGHT as isize);
+                        settings.offset += settings.row_length() * settings.grid_height();
                     }
 
                     if input.key_pressed(VirtualKeyCode::Key1)
@@ -218,8 +253,7 @@ pub fn run(options: CliOptions) -> Result<()> {
                         settings.offset = 0;
                         settings.offset_fine = 0;
                     } else if input.key_pressed(VirtualKeyCode::End) {
-                        settings.offset = settings.buffer_length
-                            - settings.width * (HEIGHT as isize) * settings.stride;
+                        settings.offset = settings.end_offset();
                         settings.offset_fine = 0;
                     }
                 }
@@ -232,14 +266,41 @@ pub fn run(options: CliOptions) -> Result<()> {
                         } else if input.held_alt() {
                             settings.width += scroll;
                         } else {
-                            settings.offset -=
-                                offset_factor * scroll * settings.width * settings.stride;
+                            settings.offset = settings.offset.saturating_add_signed(
+                                -(offset_factor as i64)
+                                    * (scroll as i64)
+                                    * (settings.row_length() as i64),
+                            );
                         }
                     }
 
+                    // Keep the last position while the mouse is over the GUI, such that the
+                    // inspector can be read
+                    if let Some((x, y)) = input.mouse() {
+                        settings.hovered_position = settings.position_at(x as u64, y as u64);
+                    }
+
                     if let Some((x, y)) = input.mouse() {
                         if input.mouse_pressed(0) {
-                            if input.held_shift() {
+                            if settings.canvas_mode == CanvasMode::Trigram {
+                                mouse_drag_action = MouseDragAction::ControlRotation {
+                                    start_x: x,
+                                    start_y: y,
+                                    start_rotation: settings.trigram_rotation,
+                                };
+                            } else if input.held_control() {
+                                let rectangular = input.held_shift();
+                                settings.selection = settings.selection_between(
+                                    (x as u64, y as u64),
+                                    (x as u64, y as u64),
+                                    rectangular,
+                                );
+                                mouse_drag_action = MouseDragAction::Select {
+                                    start_x: x,
+                                    start_y: y,
+                                    rectangular,
+                                };
+                            } else if input.held_shift() {
                                 mouse_drag_action = MouseDragAction::ControlOffsetFine {
                                     start_x: x,
                                     start_offset_fine: settings.offset_fine,
@@ -271,20 +332,21 @@ pub fn run(options: CliOptions) -> Result<()> {
                                     start_offset,
                                 } => {
                                     let delta_y = (y - start_y) / zoom_factor;
-                                    let min_offset =
-                                        start_offset % (settings.width * settings.stride);
-                                    settings.offset = min_offset.max(
-                                        start_offset
-                                            - (delta_y as isize) * settings.width * settings.stride,
-                                    );
+                                    let row_length = settings.row_length();
+                                    let min_offset = start_offset % row_length;
+                                    settings.offset =
+                                        min_offset.max(start_offset.saturating_add_signed(
+                                            -(delta_y as i64) * (row_length as i64),
+                                        ));
                                 }
                                 MouseDragAction::ControlOffsetFine {
                                     start_x,
                                     start_offset_fine,
                                 } => {
                                     let delta_x = (x - start_x) / zoom_factor;
-                                    settings.offset_fine =
-                                        start_offset_fine - (delta_x as isize) * settings.stride;
+                                    settings.offset_fine = start_offset_fine.saturating_add_signed(
+                                        -(delta_x as i64) * (settings.stride as i64),
+                                    );
                                 }
                                 MouseDragAction::ControlWidth {
                                     start_x,
@@ -293,26 +355,40 @@ pub fn run(options: CliOptions) -> Result<()> {
                                     let delta_x = (x - start_x) / zoom_factor;
                                     settings.width = start_width + (delta_x as isize);
                                 }
+                                MouseDragAction::ControlRotation {
+                                    start_x,
+                                    start_y,
+                                    start_rotation: (yaw, pitch),
+                                } => {
+                                    let radians_per_pixel = 0.01;
+                                    settings.trigram_rotation = (
+                                        yaw + (x - start_x) * radians_per_pixel,
+                                        (pitch + (y - start_y) * radians_per_pixel)
+                                            .clamp(-FRAC_PI_2, FRAC_PI_2),
+                                    );
+                                }
+                                MouseDragAction::Select {
+                                    start_x,
+                                    start_y,
+                                    rectangular,
+                                } => {
+                                    // Keep the previous selection if the mouse is outside
+                                    // of the data
+                                    if let Some(selection) = settings.selection_between(
+                                        (start_x as u64, start_y as u64),
+                                        (x as u64, y as u64),
+                                        rectangular,
+                                    ) {
+                                        settings.selection = Some(selection);
+                                    }
+                                }
                                 MouseDragAction::Nothing => {}
                             }
                         }
                     }
                 }
 
-                settings.zoom = settings.zoom.max(settings.zoom_range.0);
-                settings.zoom = settings.zoom.min(settings.zoom_range.1);
-
-                settings.width = settings.width.max(1);
-                settings.width = settings.width.min(settings.max_width());
-
-                settings.offset = settings.offset.max(0);
-                settings.offset = settings.offset.min(settings.buffer_length);
-
-                settings.offset_fine = settings.offset_fine.max(0);
-                settings.offset_fine = settings.offset_fine.min(settings.max_offset_fine());
-
-                settings.stride = settings.stride.max(1);
-                settings.stride = settings.stride.min(settings.max_stride);
+                settings.clamp();
 
                 // Update the scale factor
                 if let Some(scale_factor) = input.scale_factor() {
@@ -321,13 +397,79 @@ pub fn run(options: CliOptions) -> Result<()> {
 
                 // Resize the window
                 if let Some(size) = input.window_resized() {
-                    let _ = pixels.resize_surface(size.width, size.height);
+                    if size.width > 0 && size.height > 0 {
+                        let _ = pixels.resize_surface(size.width, size.height);
+                        let _ = pixels.resize_buffer(size.width, size.height);
+                        frame_width = size.width as usize;
+                        settings.canvas_height = size.height as isize;
+                        // The canvas width is updated by the GUI, which knows the panel sizes
+                    }
                     gui.resize(size.width, size.height);
                 }
             }
 
+            // The resized pixel buffer has been cleared
+            if input.window_resized().is_some() {
+                binocle.invalidate();
+            }
+
+            let building_minimap = binocle.update_minimap();
+            let searching = binocle.update_search();
+            binocle.update_inspector();
+            binocle.update_auto_width();
+            binocle.update_format_guesses();
+            binocle.handle_selection_action();
+            binocle.save_annotations();
+            if let Some(path) = binocle.handle_session_action() {
+                // The session belongs to another file, which replaces the current one
+                match Binocle::from_session(&path) {
+                    Ok(mut opened) => {
+                        opened.settings.canvas_width = binocle.settings.canvas_width;
+                        opened.settings.canvas_height = binocle.settings.canvas_height;
+                        opened.settings.session_status =
+                            format!("Opened session '{}'", path.display());
+                        binocle = opened;
+                        window.set_title(&window_title(binocle.filename(), None));
+                    }
+                    Err(e) => binocle.settings.session_status = format!("{:#}", e),
+                }
+            }
             binocle.update_hex_view();
-            window.request_redraw();
+
+            let repaint_after = gui.repaint_after();
+            if window_events
+                || building_minimap
+                || searching
+                || binocle.needs_redraw()
+                || repaint_after.is_zero()
+            {
+                window.request_redraw();
+            }
+            window_events = false;
+
+            // Sleep until the next event. Live input and background work (searches and the
+            // minimap) are checked for new data regularly.
+            let mut timeout = repaint_after;
+            if binocle.settings.live_input || binocle.is_followed() || searching || building_minimap
+            {
+                timeout = timeout.min(LIVE_INPUT_INTERVAL);
+            }
+            *control_flow = match Instant::now().checked_add(timeout) {
+                Some(deadline) if timeout < Duration::MAX => ControlFlow::WaitUntil(deadline),
+                _ => ControlFlow::Wait,
+            };
         }
     });
 }
+
+fn window_title(filename: &str, pid: Option<u32>) -> String {
+    let name = match pid {
+        Some(pid) => format!("pid {}", pid),
+        None if filename == "-" => "<stdin>".into(),
+        None => Path::new(filename)
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "<unknown>".into()),
+    };
+    format!("binocle - {}", name)
+}
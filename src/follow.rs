@@ -0,0 +1,93 @@
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls a file for modifications (size or modification time).
+pub struct FileWatcher {
+    path: PathBuf,
+    last_poll: Instant,
+    last_state: Option<(u64, SystemTime)>,
+}
+
+impl FileWatcher {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        let mut watcher = FileWatcher {
+            path: path.into(),
+            last_poll: Instant::now(),
+            last_state: None,
+        };
+        watcher.last_state = watcher.state();
+        watcher
+    }
+
+    fn state(&self) -> Option<(u64, SystemTime)> {
+        let metadata = fs::metadata(&self.path).ok()?;
+        Some((metadata.len(), metadata.modified().ok()?))
+    }
+
+    /// Returns `true` if the file has changed since the last call. The file system is queried at
+    /// most once per `POLL_INTERVAL`.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let state = self.state();
+        if state.is_some() && state != self.last_state {
+            self.last_state = state;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Compute the (sorted, non-overlapping) byte ranges in which `new` differs from `old`. Bytes
+/// beyond the end of `old` count as changed.
+pub fn changed_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = None;
+
+    for (i, (a, b)) in old.iter().zip(new.iter()).enumerate() {
+        match (a != b, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let common_length = old.len().min(new.len());
+    if let Some(s) = start {
+        ranges.push(s..common_length);
+    }
+
+    if new.len() > common_length {
+        match ranges.last_mut() {
+            Some(last) if last.end == common_length => last.end = new.len(),
+            _ => ranges.push(common_length..new.len()),
+        }
+    }
+
+    ranges
+}
+
+#[test]
+fn changed_ranges_basic() {
+    assert_eq!(
+        changed_ranges(&[1, 2, 3], &[1, 2, 3]),
+        Vec::<Range<usize>>::new()
+    );
+    assert_eq!(changed_ranges(&[1, 2, 3], &[1, 0, 3]), vec![1..2]);
+    assert_eq!(changed_ranges(&[1, 2, 3], &[0, 0, 3]), vec![0..2]);
+    assert_eq!(changed_ranges(&[1, 2, 3], &[0, 2, 0]), vec![0..1, 2..3]);
+    assert_eq!(changed_ranges(&[1, 2, 3], &[1, 2, 3, 4, 5]), vec![3..5]);
+    assert_eq!(changed_ranges(&[1, 2, 3], &[1, 2, 0, 4]), vec![2..4]);
+    assert_eq!(changed_ranges(&[1, 2, 3], &[1]), Vec::<Range<usize>>::new());
+}
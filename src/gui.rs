@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use egui::{ClippedPrimitive, TexturesDelta, WidgetText};
 
 use egui_wgpu::renderer::ScreenDescriptor;
@@ -9,8 +11,14 @@ use pixels::{wgpu, PixelsContext};
 use winit::window::Window;
 
 use crate::{
-    datatype::{Endianness, Signedness},
-    settings::{GuiDatatype, PixelStyle, Settings, HEIGHT},
+    datatype::{BitOrder, Endianness, Signedness},
+    inspector::Inspection,
+    layout::Layout,
+    search::SearchKind,
+    selection::SelectionAction,
+    session::SessionAction,
+    settings::{CanvasMode, GuiDatatype, PixelStyle, Settings},
+    style::rgba_from_color,
 };
 
 pub struct Gui {
@@ -21,6 +29,8 @@ pub struct Gui {
     paint_jobs: Vec<ClippedPrimitive>,
     renderer: Renderer,
     textures: TexturesDelta,
+    /// Time after which egui wants to be repainted (e.g. for animations)
+    repaint_after: Duration,
 }
 
 impl Gui {
@@ -51,6 +61,7 @@ impl Gui {
             renderer,
             paint_jobs: Vec::new(),
             textures,
+            repaint_after: Duration::MAX,
         }
     }
 
@@ -76,246 +87,455 @@ impl Gui {
             Self::ui(egui_ctx, settings);
         });
 
+        self.repaint_after = output.repaint_after;
         self.textures.append(output.textures_delta);
         self.egui_state
             .handle_platform_output(window, &self.egui_ctx, output.platform_output);
         self.paint_jobs = self.egui_ctx.tessellate(output.shapes);
     }
 
+    pub fn repaint_after(&self) -> Duration {
+        self.repaint_after
+    }
+
     fn ui(ctx: &egui::Context, settings: &mut Settings) {
         let max_offset_fine = settings.max_offset_fine();
         let max_width = settings.max_width();
+        let length_in_units = settings.length_in_units();
         egui::SidePanel::right("Settings").show(ctx, |ui| {
-            ui.heading("Layout");
-            ui.add(
-                egui::Slider::new(
-                    &mut settings.zoom,
-                    settings.zoom_range.0..=settings.zoom_range.1,
-                )
-                .clamp_to_range(true)
-                .smart_aim(false)
-                .text("zoom"),
-            );
-            ui.add(
-                egui::Slider::new(&mut settings.width, 1..=max_width)
-                    .clamp_to_range(true)
-                    .smart_aim(false)
-                    .text("width"),
-            );
-            ui.horizontal(|ui| {
-                if ui
-                    .add_enabled(settings.width % 2 == 0, egui::Button::new("÷ 2"))
-                    .clicked()
-                {
-                    settings.width /= 2;
-                }
-                if ui
-                    .add_enabled(settings.width % 3 == 0, egui::Button::new("÷ 3"))
-                    .clicked()
-                {
-                    settings.width /= 3;
-                }
-                if ui
-                    .add_enabled(settings.width % 5 == 0, egui::Button::new("÷ 5"))
-                    .clicked()
-                {
-                    settings.width /= 5;
-                }
-                if ui
-                    .add_enabled(settings.width % 7 == 0, egui::Button::new("÷ 7"))
-                    .clicked()
-                {
-                    settings.width /= 7;
-                }
-                if ui.button("× 2").clicked() && 2 * settings.width <= max_width {
-                    settings.width *= 2;
-                }
-            });
-            ui.add(
-                egui::Slider::new(&mut settings.stride, 1..=settings.max_stride)
-                    .clamp_to_range(true)
-                    .smart_aim(false)
-                    .text("stride"),
-            );
-            ui.separator();
-
-            ui.heading("Offset");
-            ui.add(
-                egui::Slider::new(&mut settings.offset, 0..=settings.buffer_length)
-                    .clamp_to_range(true)
-                    .smart_aim(false)
-                    .text("coarse"),
-            );
-            ui.add(
-                egui::Slider::new(&mut settings.offset_fine, 0..=max_offset_fine)
-                    .clamp_to_range(true)
-                    .smart_aim(false)
-                    .text("fine"),
-            );
-            ui.separator();
-
-            ui.heading("Pixel style");
-            ui.label("Single byte");
-            ui.horizontal_wrapped(|ui| {
-                ui.selectable_value(&mut settings.pixel_style, PixelStyle::Colorful, "Default");
-                ui.selectable_value(&mut settings.pixel_style, PixelStyle::Category, "Category");
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::Grayscale,
-                    "Grayscale",
-                );
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::GradientMagma,
-                    "Magma",
-                );
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::GradientPlasma,
-                    "Plasma",
-                );
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::GradientViridis,
-                    "Viridis",
-                );
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::GradientRainbow,
-                    "Rainbow",
-                );
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::GradientTurbo,
-                    "Turbo",
-                );
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::GradientCubehelix,
-                    "Cubehelix",
-                );
-                ui.selectable_value(
-                    &mut settings.pixel_style,
-                    PixelStyle::Entropy,
-                    "Entropy (slow)",
-                );
-            });
-
-            ui.label("Multi-byte");
-            ui.horizontal(|ui| {
-                ui.selectable_value(&mut settings.pixel_style, PixelStyle::Rgba, "RGBA");
-                ui.selectable_value(&mut settings.pixel_style, PixelStyle::Abgr, "ABGR");
-                ui.selectable_value(&mut settings.pixel_style, PixelStyle::Rgb, "RGB");
-                ui.selectable_value(&mut settings.pixel_style, PixelStyle::Bgr, "BGR");
-            });
-            ui.selectable_value(&mut settings.pixel_style, PixelStyle::Datatype, "Datatype");
-            ui.separator();
-            ui.label("Datatype");
-            ui.vertical(|ui| {
-                ui.set_enabled(settings.pixel_style == PixelStyle::Datatype);
+            // The sections do not fit into smaller windows
+            egui::ScrollArea::vertical()
+                .id_source("settings")
+                .show(ui, |ui| {
+                    ui.heading("Canvas");
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut settings.canvas_mode, CanvasMode::Grid, "Grid");
+                        ui.selectable_value(
+                            &mut settings.canvas_mode,
+                            CanvasMode::Digraph,
+                            "Digraph",
+                        )
+                        .on_hover_text("Density of consecutive byte pairs in the visible range");
+                        ui.selectable_value(
+                            &mut settings.canvas_mode,
+                            CanvasMode::Trigram,
+                            "Trigram",
+                        )
+                        .on_hover_text(
+                            "Point cloud of consecutive byte triples in the visible range. \
+                         Drag with the mouse to rotate.",
+                        );
+                    });
+                    ui.separator();
 
-                ui.horizontal_wrapped(|ui| {
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.datatype,
-                        GuiDatatype::Integer8,
-                        "Integer (8 bit)",
-                    );
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.datatype,
-                        GuiDatatype::Integer16,
-                        "Integer (16 bit)",
+                    ui.heading("Layout");
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut settings.layout, Layout::RowMajor, "Rows");
+                        ui.selectable_value(&mut settings.layout, Layout::ColumnMajor, "Columns");
+                        ui.selectable_value(&mut settings.layout, Layout::Hilbert, "Hilbert");
+                        ui.selectable_value(&mut settings.layout, Layout::ZOrder, "Z-order");
+                    });
+                    ui.horizontal(|ui| {
+                        let mut bit_order = settings.bit_order;
+                        ui.selectable_value(&mut bit_order, None, "Bytes");
+                        ui.selectable_value(
+                            &mut bit_order,
+                            Some(BitOrder::MsbFirst),
+                            "Bits (MSB first)",
+                        );
+                        ui.selectable_value(
+                            &mut bit_order,
+                            Some(BitOrder::LsbFirst),
+                            "Bits (LSB first)",
+                        );
+                        if bit_order != settings.bit_order {
+                            settings.set_bit_order(bit_order);
+                        }
+                    });
+                    ui.add(
+                        egui::Slider::new(
+                            &mut settings.zoom,
+                            settings.zoom_range.0..=settings.zoom_range.1,
+                        )
+                        .clamp_to_range(true)
+                        .smart_aim(false)
+                        .text("zoom"),
                     );
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.datatype,
-                        GuiDatatype::Integer32,
-                        "Integer (32 bit)",
+                    ui.add(
+                        egui::Slider::new(&mut settings.width, 1..=max_width)
+                            .clamp_to_range(true)
+                            .smart_aim(false)
+                            .text("width"),
                     );
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.datatype,
-                        GuiDatatype::Integer64,
-                        "Integer (64 bit)",
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(settings.width % 2 == 0, egui::Button::new("÷ 2"))
+                            .clicked()
+                        {
+                            settings.width /= 2;
+                        }
+                        if ui
+                            .add_enabled(settings.width % 3 == 0, egui::Button::new("÷ 3"))
+                            .clicked()
+                        {
+                            settings.width /= 3;
+                        }
+                        if ui
+                            .add_enabled(settings.width % 5 == 0, egui::Button::new("÷ 5"))
+                            .clicked()
+                        {
+                            settings.width /= 5;
+                        }
+                        if ui
+                            .add_enabled(settings.width % 7 == 0, egui::Button::new("÷ 7"))
+                            .clicked()
+                        {
+                            settings.width /= 7;
+                        }
+                        if ui.button("× 2").clicked() && 2 * settings.width <= max_width {
+                            settings.width *= 2;
+                        }
+                    });
+                    ui.horizontal_wrapped(|ui| {
+                        if ui
+                            .button("auto width")
+                            .on_hover_text("Find periods in the visible data")
+                            .clicked()
+                        {
+                            settings.auto_width_requested = true;
+                        }
+                        for candidate in &settings.width_candidates {
+                            let width = candidate.length as isize;
+                            if ui
+                                .selectable_label(settings.width == width, width.to_string())
+                                .on_hover_text(format!("autocorrelation: {:.2}", candidate.score))
+                                .clicked()
+                            {
+                                settings.width = width;
+                            }
+                        }
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut settings.stride, 1..=settings.max_stride)
+                            .clamp_to_range(true)
+                            .smart_aim(false)
+                            .text("stride"),
                     );
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.datatype,
-                        GuiDatatype::Float32,
-                        "Float (32 bit)",
+                    ui.separator();
+
+                    if !settings.mappings.is_empty() {
+                        ui.heading("Memory mappings");
+                        egui::ScrollArea::vertical()
+                            .max_height(150.0)
+                            .show(ui, |ui| {
+                                for (index, label) in settings.mappings.iter().enumerate() {
+                                    ui.selectable_value(
+                                        &mut settings.selected_mapping,
+                                        Some(index),
+                                        WidgetText::from(label).monospace(),
+                                    );
+                                }
+                            });
+                        ui.separator();
+                    }
+
+                    ui.heading("Offset");
+                    ui.add(
+                        egui::Slider::new(&mut settings.offset, 0..=length_in_units)
+                            .clamp_to_range(true)
+                            .smart_aim(false)
+                            .text("coarse"),
                     );
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.datatype,
-                        GuiDatatype::Float64,
-                        "Float (64 bit)",
+                    ui.add(
+                        egui::Slider::new(&mut settings.offset_fine, 0..=max_offset_fine)
+                            .clamp_to_range(true)
+                            .smart_aim(false)
+                            .text("fine"),
                     );
-                });
-                ui.label("Signedness");
-                ui.horizontal(|ui| {
-                    // Only enable for datatypes that have 'signedness'
-                    ui.set_enabled(match settings.datatype_settings.datatype {
-                        GuiDatatype::Integer8
-                        | GuiDatatype::Integer16
-                        | GuiDatatype::Integer32
-                        | GuiDatatype::Integer64 => true,
-                        GuiDatatype::Float32 | GuiDatatype::Float64 => false,
+                    if settings.live_input {
+                        ui.checkbox(&mut settings.follow_tail, "follow end of file");
+                        ui.checkbox(&mut settings.highlight_changes, "highlight changes");
+                    }
+                    ui.separator();
+
+                    ui.heading("Pixel style");
+                    ui.label("Single byte");
+                    ui.horizontal_wrapped(|ui| {
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::Colorful,
+                            "Default",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::Category,
+                            "Category",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::Grayscale,
+                            "Grayscale",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::GradientMagma,
+                            "Magma",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::GradientPlasma,
+                            "Plasma",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::GradientViridis,
+                            "Viridis",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::GradientRainbow,
+                            "Rainbow",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::GradientTurbo,
+                            "Turbo",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::GradientCubehelix,
+                            "Cubehelix",
+                        );
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::Entropy,
+                            "Entropy",
+                        );
                     });
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.signedness,
-                        Signedness::Unsigned,
-                        "Unsigned",
-                    );
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.signedness,
-                        Signedness::Signed,
-                        "Signed",
-                    );
-                });
-                ui.label("Endianness");
-                ui.horizontal(|ui| {
-                    // Only enable for datatypes that are multi-byte
-                    ui.set_enabled(match settings.datatype_settings.datatype {
-                        GuiDatatype::Integer8 => false,
-                        GuiDatatype::Integer16
-                        | GuiDatatype::Integer32
-                        | GuiDatatype::Integer64
-                        | GuiDatatype::Float32
-                        | GuiDatatype::Float64 => true,
+                    if settings.pixel_style == PixelStyle::Entropy {
+                        ui.add(
+                            egui::Slider::new(&mut settings.entropy_window_size, 4..=4096)
+                                .logarithmic(true)
+                                .clamp_to_range(true)
+                                .text("entropy window"),
+                        );
+                    }
+
+                    ui.label("Multi-byte");
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut settings.pixel_style, PixelStyle::Rgba, "RGBA");
+                        ui.selectable_value(&mut settings.pixel_style, PixelStyle::Abgr, "ABGR");
+                        ui.selectable_value(&mut settings.pixel_style, PixelStyle::Rgb, "RGB");
+                        ui.selectable_value(&mut settings.pixel_style, PixelStyle::Bgr, "BGR");
+                        ui.selectable_value(
+                            &mut settings.pixel_style,
+                            PixelStyle::Rgb565,
+                            "RGB565",
+                        );
                     });
                     ui.selectable_value(
-                        &mut settings.datatype_settings.endianness,
-                        Endianness::Little,
-                        "Little Endian",
-                    );
-                    ui.selectable_value(
-                        &mut settings.datatype_settings.endianness,
-                        Endianness::Big,
-                        "Big Endian",
+                        &mut settings.pixel_style,
+                        PixelStyle::Datatype,
+                        "Datatype",
                     );
-                });
-                ui.label("");
-                ui.horizontal(|ui| {
-                    ui.label("min:");
-                    ui.add(egui::DragValue::new(&mut settings.value_range.0).speed(10.0));
-                    ui.label("max:");
-                    ui.add(egui::DragValue::new(&mut settings.value_range.1).speed(10.0));
-                });
-            });
+                    ui.separator();
+                    ui.label("Datatype");
+                    ui.vertical(|ui| {
+                        ui.set_enabled(settings.pixel_style == PixelStyle::Datatype);
+
+                        ui.horizontal_wrapped(|ui| {
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.datatype,
+                                GuiDatatype::Integer8,
+                                "Integer (8 bit)",
+                            );
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.datatype,
+                                GuiDatatype::Integer16,
+                                "Integer (16 bit)",
+                            );
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.datatype,
+                                GuiDatatype::Integer32,
+                                "Integer (32 bit)",
+                            );
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.datatype,
+                                GuiDatatype::Integer64,
+                                "Integer (64 bit)",
+                            );
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.datatype,
+                                GuiDatatype::Float32,
+                                "Float (32 bit)",
+                            );
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.datatype,
+                                GuiDatatype::Float64,
+                                "Float (64 bit)",
+                            );
+                        });
+                        ui.label("Signedness");
+                        ui.horizontal(|ui| {
+                            // Only enable for datatypes that have 'signedness'
+                            ui.set_enabled(match settings.datatype_settings.datatype {
+                                GuiDatatype::Integer8
+                                | GuiDatatype::Integer16
+                                | GuiDatatype::Integer32
+                                | GuiDatatype::Integer64 => true,
+                                GuiDatatype::Float32 | GuiDatatype::Float64 => false,
+                            });
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.signedness,
+                                Signedness::Unsigned,
+                                "Unsigned",
+                            );
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.signedness,
+                                Signedness::Signed,
+                                "Signed",
+                            );
+                        });
+                        ui.label("Endianness");
+                        ui.horizontal(|ui| {
+                            // Only enable for datatypes that are multi-byte
+                            ui.set_enabled(match settings.datatype_settings.datatype {
+                                GuiDatatype::Integer8 => false,
+                                GuiDatatype::Integer16
+                                | GuiDatatype::Integer32
+                                | GuiDatatype::Integer64
+                                | GuiDatatype::Float32
+                                | GuiDatatype::Float64 => true,
+                            });
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.endianness,
+                                Endianness::Little,
+                                "Little Endian",
+                            );
+                            ui.selectable_value(
+                                &mut settings.datatype_settings.endianness,
+                                Endianness::Big,
+                                "Big Endian",
+                            );
+                        });
+                        ui.label("");
+                        ui.horizontal(|ui| {
+                            ui.label("min:");
+                            ui.add(egui::DragValue::new(&mut settings.value_range.0).speed(10.0));
+                            ui.label("max:");
+                            ui.add(egui::DragValue::new(&mut settings.value_range.1).speed(10.0));
+                        });
+                    });
+
+                    ui.separator();
+
+                    ui.checkbox(&mut settings.hex_view_visible, "hex view");
+                    ui.separator();
+
+                    ui.heading("Search");
+                    Self::search(ui, settings);
+                    ui.separator();
+
+                    ui.heading("Selection");
+                    Self::selection(ui, settings);
+                    ui.separator();
+
+                    ui.heading("Session");
+                    ui.horizontal(|ui| {
+                        ui.label("file:");
+                        ui.text_edit_singleline(&mut settings.session_path);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Save session").clicked() {
+                            settings.session_action = Some(SessionAction::Save);
+                        }
+                        if ui.button("Open session").clicked() {
+                            settings.session_action = Some(SessionAction::Open);
+                        }
+                    });
+                    if !settings.session_status.is_empty() {
+                        ui.label(&settings.session_status);
+                    }
+                    ui.separator();
+
+                    ui.heading("Annotations");
+                    Self::annotations(ui, settings);
+                    ui.separator();
+
+                    ui.heading("Histogram");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut settings.histogram_log_scale, "log scale");
+                        if let Some(byte) = settings.highlighted_byte {
+                            ui.label(format!("highlighting 0x{:02x}", byte));
+                            if ui.button("clear").clicked() {
+                                settings.highlighted_byte = None;
+                            }
+                        }
+                    });
+                    Self::histogram(ui, settings);
+                    ui.separator();
 
-            ui.separator();
-
-            ui.checkbox(&mut settings.hex_view_visible, "hex view");
-            ui.separator();
-
-            ui.heading("Information");
-            let file_size = settings
-                .buffer_length
-                .file_size(file_size_opts::BINARY)
-                .unwrap();
-            ui.label(format!("file size: {}", file_size));
-            let zoom_factor = settings.zoom_factor();
-            let grid_size = (settings.width * (HEIGHT as isize) * settings.stride / zoom_factor)
-                .file_size(file_size_opts::BINARY)
-                .unwrap();
-            ui.label(format!("grid size: {}", grid_size));
+                    ui.heading("Information");
+                    let file_size = settings
+                        .buffer_length
+                        .file_size(file_size_opts::BINARY)
+                        .unwrap();
+                    if let Some(compression) = settings.compression {
+                        let compressed_size = settings
+                            .compressed_length
+                            .file_size(file_size_opts::BINARY)
+                            .unwrap();
+                        ui.label(format!(
+                            "compressed size: {} ({})",
+                            compressed_size, compression
+                        ));
+                        ui.label(format!("decompressed size: {}", file_size));
+                    } else {
+                        ui.label(format!("file size: {}", file_size));
+                    }
+                    if let Some(note) = &settings.decompression_error {
+                        ui.label(note);
+                    }
+                    if settings.base_offset > 0 {
+                        ui.label(format!(
+                            "loaded range: 0x{:x} - 0x{:x}",
+                            settings.base_offset,
+                            settings.base_offset + settings.buffer_length
+                        ));
+                    }
+                    let view_offset = settings.view_offset();
+                    if settings.bit_order.is_some() {
+                        ui.label(format!(
+                            "offset: 0x{:x} (bit {})",
+                            settings.base_offset + view_offset / 8,
+                            view_offset % 8
+                        ));
+                    } else {
+                        ui.label(format!(
+                            "offset: 0x{:x}",
+                            settings.base_offset + view_offset
+                        ));
+                    }
+                    let zoom_factor = settings.zoom_factor();
+                    let grid_size = (settings.width * settings.canvas_height * settings.stride
+                        / zoom_factor
+                        / settings.units_per_byte() as isize)
+                        .file_size(file_size_opts::BINARY)
+                        .unwrap();
+                    ui.label(format!("grid size: {}", grid_size));
+
+                    if let Some(inspection) = &settings.inspection {
+                        ui.separator();
+                        Self::inspector(ui, inspection);
+                    }
+                });
         });
 
+        egui::SidePanel::right("Minimap")
+            .resizable(false)
+            .exact_width(24.0)
+            .show(ctx, |ui| Self::minimap(ui, settings));
+
         if settings.hex_view_visible {
             egui::TopBottomPanel::bottom("hex view").show(ctx, |ui| {
                 ui.horizontal(|ui| {
@@ -331,10 +551,362 @@ impl Gui {
             });
         }
 
+        // The canvas covers the part of the window that is not taken by the side panels
+        let canvas_width = ctx.available_rect().right() * ctx.pixels_per_point();
+        settings.canvas_width = (canvas_width.floor() as isize).max(1);
+
+        if let Some(text) = settings.clipboard.take() {
+            ctx.output_mut(|output| output.copied_text = text);
+        }
+
         settings.gui_wants_keyboard = ctx.wants_keyboard_input();
         settings.gui_wants_mouse = ctx.wants_pointer_input();
     }
 
+    fn search(ui: &mut egui::Ui, settings: &mut Settings) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut settings.search_kind, SearchKind::Hex, "Hex")
+                .on_hover_text("Hexadecimal bytes with ?? as wildcard, e.g. 7f 45 4c 46 ?? 01");
+            ui.selectable_value(&mut settings.search_kind, SearchKind::Text, "Text")
+                .on_hover_text("ASCII / UTF-8 string");
+            ui.selectable_value(&mut settings.search_kind, SearchKind::Utf16, "UTF-16")
+                .on_hover_text("UTF-16 string (little and big endian)");
+            ui.selectable_value(&mut settings.search_kind, SearchKind::Regex, "Regex")
+                .on_hover_text("Regular expression on bytes");
+        });
+        ui.horizontal(|ui| {
+            let response = ui.text_edit_singleline(&mut settings.search_query);
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("search").clicked() || submitted {
+                settings.search_requested = true;
+            }
+        });
+
+        if settings.search_status.is_empty() {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label(&settings.search_status);
+            let has_matches = !settings.search_matches.is_empty();
+            if ui
+                .add_enabled(has_matches, egui::Button::new("◀"))
+                .clicked()
+            {
+                settings.go_to_next_match(false);
+            }
+            if ui
+                .add_enabled(has_matches, egui::Button::new("▶"))
+                .clicked()
+            {
+                settings.go_to_next_match(true);
+            }
+        });
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .id_source("search results")
+            .max_height(120.0)
+            .show_rows(ui, row_height, settings.search_matches.len(), |ui, rows| {
+                for index in rows {
+                    let found = &settings.search_matches[index];
+                    let label = format!(
+                        "0x{:x} ({} bytes)",
+                        settings.base_offset + found.start,
+                        found.end - found.start
+                    );
+                    let current = settings.search_current == Some(index);
+                    if ui.selectable_label(current, label).clicked() {
+                        clicked = Some(index);
+                    }
+                }
+            });
+        if let Some(index) = clicked {
+            settings.go_to_match(index);
+        }
+    }
+
+    fn selection(ui: &mut egui::Ui, settings: &mut Settings) {
+        let selection = match &settings.selection {
+            Some(selection) => selection,
+            None => {
+                ui.label("Ctrl + drag: select a range");
+                ui.label("Ctrl + Shift + drag: select a rectangle");
+                return;
+            }
+        };
+
+        let start = settings.base_offset + selection.start();
+        let end = settings.base_offset + selection.end();
+        ui.label(format!("start: 0x{:x}", start));
+        ui.label(format!("end: 0x{:x}", end));
+        ui.label(format!(
+            "length: {} (0x{:x})",
+            selection.len(),
+            selection.len()
+        ));
+
+        ui.horizontal(|ui| {
+            ui.label("file:");
+            ui.text_edit_singleline(&mut settings.export_path);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("save").clicked() {
+                settings.selection_action = Some(SelectionAction::Save);
+            }
+            if ui.button("copy as hex").clicked() {
+                settings.selection_action = Some(SelectionAction::CopyHex);
+            }
+            if ui.button("copy as C array").clicked() {
+                settings.selection_action = Some(SelectionAction::CopyCArray);
+            }
+            if ui.button("clear").clicked() {
+                settings.selection = None;
+                settings.selection_status.clear();
+            }
+        });
+        if !settings.selection_status.is_empty() {
+            ui.label(&settings.selection_status);
+        }
+
+        if ui
+            .button("guess pixel format")
+            .on_hover_text("Rank pixel formats by how smooth the selected data looks in them")
+            .clicked()
+        {
+            settings.format_detection_requested = true;
+        }
+        let mut chosen = None;
+        ui.horizontal_wrapped(|ui| {
+            for guess in &settings.format_guesses {
+                let text = format!("{} ({:.2})", guess.format.name(), guess.score);
+                if ui
+                    .button(text)
+                    .on_hover_text(format!(
+                        "stride {}, first pixel at +{}",
+                        guess.format.stride(),
+                        guess.phase
+                    ))
+                    .clicked()
+                {
+                    chosen = Some(*guess);
+                }
+            }
+        });
+        if let Some(guess) = chosen {
+            settings.apply_pixel_format(guess);
+        }
+    }
+
+    /// Bookmarks and labeled regions, with controls to add, recolor, remove and jump to them.
+    fn annotations(ui: &mut egui::Ui, settings: &mut Settings) {
+        ui.horizontal(|ui| {
+            ui.color_edit_button_srgb(&mut settings.annotation_color);
+            ui.add(egui::TextEdit::singleline(&mut settings.annotation_label).hint_text("label"));
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .button("bookmark view")
+                .on_hover_text("Bookmark the first byte that is shown")
+                .clicked()
+            {
+                settings.add_bookmark();
+            }
+            let has_selection = settings.selection.is_some();
+            if ui
+                .add_enabled(has_selection, egui::Button::new("annotate selection"))
+                .clicked()
+            {
+                settings.annotate_selection();
+            }
+        });
+
+        let mut jump_to = None;
+        let mut remove = None;
+        let mut changed = false;
+        egui::ScrollArea::vertical()
+            .id_source("annotations")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                for (index, annotation) in settings.annotations.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        changed |= ui.color_edit_button_srgb(&mut annotation.color).changed();
+                        let text = if annotation.is_bookmark() {
+                            format!("0x{:x} {}", annotation.start, annotation.label)
+                        } else {
+                            format!(
+                                "0x{:x} +{} {}",
+                                annotation.start, annotation.length, annotation.label
+                            )
+                        };
+                        if ui.link(text).on_hover_text("Go to").clicked() {
+                            jump_to = Some(index);
+                        }
+                        if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                }
+            });
+
+        if let Some(index) = jump_to {
+            settings.go_to_annotation(index);
+        }
+        if let Some(index) = remove {
+            settings.annotations.remove(index);
+            changed = true;
+        }
+        if changed {
+            settings.annotations_version += 1;
+        }
+        if !settings.annotations_status.is_empty() {
+            ui.label(&settings.annotations_status);
+        }
+    }
+
+    /// The data under the mouse cursor, decoded as numbers and text.
+    fn inspector(ui: &mut egui::Ui, inspection: &Inspection) {
+        ui.heading("Inspector");
+        match inspection.bit {
+            Some(bit) => ui.label(format!("offset: 0x{:x} (bit {})", inspection.offset, bit)),
+            None => ui.label(format!("offset: 0x{:x}", inspection.offset)),
+        };
+        ui.label(WidgetText::from(inspection.hex()).monospace());
+
+        egui::Grid::new("inspector").striped(true).show(ui, |ui| {
+            ui.label("");
+            ui.label("little endian");
+            ui.label("big endian");
+            ui.end_row();
+
+            for (name, little_endian, big_endian) in inspection.numbers() {
+                ui.label(name);
+                ui.label(WidgetText::from(little_endian).monospace());
+                ui.label(WidgetText::from(big_endian).monospace());
+                ui.end_row();
+            }
+
+            let text = [
+                ("ASCII", inspection.ascii()),
+                ("UTF-8", inspection.utf8()),
+                ("binary", inspection.binary()),
+            ];
+            for (name, value) in text {
+                ui.label(name);
+                ui.label(WidgetText::from(value.unwrap_or_default()).monospace());
+                ui.end_row();
+            }
+        });
+    }
+
+    /// Entropy overview of the whole buffer with the visible range marked. Clicking or dragging
+    /// moves the view.
+    fn minimap(ui: &mut egui::Ui, settings: &mut Settings) {
+        let rect = ui.available_rect_before_wrap();
+        let response = ui.allocate_rect(rect, egui::Sense::click_and_drag());
+        if settings.buffer_length == 0 {
+            return;
+        }
+        let total = settings.buffer_length as f32;
+        let y_at = |position: u64| rect.top() + rect.height() * (position as f32 / total);
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        let gradient = colorgrad::magma();
+        for (i, &entropy) in settings.minimap.iter().enumerate() {
+            let start = i as u64 * settings.minimap_block_size;
+            let end = start + settings.minimap_block_size;
+            let color = rgba_from_color(gradient.at(entropy as f64));
+            painter.rect_filled(
+                egui::Rect::from_x_y_ranges(rect.x_range(), y_at(start)..=y_at(end)),
+                0.0,
+                egui::Color32::from_rgb(color[0], color[1], color[2]),
+            );
+        }
+
+        let view_start = settings.view_byte_offset();
+        let top = y_at(view_start);
+        let bottom = y_at(view_start + settings.visible_length).max(top + 2.0);
+        painter.rect_stroke(
+            egui::Rect::from_x_y_ranges(rect.x_range(), top..=bottom).shrink(1.0),
+            0.0,
+            egui::Stroke::new(2.0, egui::Color32::WHITE),
+        );
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.clicked() || response.dragged() {
+                // Center the view on the position under the pointer
+                let fraction = ((pos.y - rect.top()) / rect.height()).clamp(0.0, 1.0);
+                let position = (fraction as f64 * settings.buffer_length as f64) as u64;
+                let start = position.saturating_sub(settings.visible_length / 2);
+                let offset = start * settings.units_per_byte();
+                settings.offset = offset - offset % settings.row_length();
+            }
+        }
+    }
+
+    /// Bar chart of the byte value distribution in the visible range. Clicking a bar highlights
+    /// all occurrences of that byte value on the canvas.
+    fn histogram(ui: &mut egui::Ui, settings: &mut Settings) {
+        let size = egui::vec2(ui.available_width(), 80.0);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+        let bar_width = rect.width() / 256.0;
+
+        let scale = |count: u64| {
+            if settings.histogram_log_scale {
+                (count as f32).ln_1p()
+            } else {
+                count as f32
+            }
+        };
+        let max = settings
+            .histogram
+            .iter()
+            .copied()
+            .map(scale)
+            .fold(0.0, f32::max);
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        for (byte, &count) in settings.histogram.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let height = rect.height() * scale(count) / max;
+            let left = rect.left() + byte as f32 * bar_width;
+            let bar = egui::Rect::from_min_max(
+                egui::pos2(left, rect.bottom() - height),
+                egui::pos2(left + bar_width.max(1.0), rect.bottom()),
+            );
+            let color = if settings.highlighted_byte == Some(byte as u8) {
+                ui.visuals().selection.bg_fill
+            } else {
+                ui.visuals().widgets.inactive.fg_stroke.color
+            };
+            painter.rect_filled(bar, 0.0, color);
+        }
+
+        let byte_at = |pos: egui::Pos2| ((pos.x - rect.left()) / bar_width).clamp(0.0, 255.0) as u8;
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.clicked() {
+                let byte = byte_at(pos);
+                settings.highlighted_byte =
+                    (settings.highlighted_byte != Some(byte)).then_some(byte);
+            }
+        }
+        if let Some(pos) = response.hover_pos() {
+            let byte = byte_at(pos);
+            let character = match byte {
+                0x20..=0x7e => format!(" '{}'", byte as char),
+                _ => String::new(),
+            };
+            response.on_hover_text_at_pointer(format!(
+                "0x{:02x} ({}){}: {}",
+                byte, byte, character, settings.histogram[byte as usize]
+            ));
+        }
+    }
+
     pub fn render(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
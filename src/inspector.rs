@@ -0,0 +1,99 @@
+use std::convert::TryInto;
+
+/// The bytes at the position under the mouse cursor, decoded in various ways.
+pub struct Inspection {
+    /// Absolute position in the file
+    pub offset: u64,
+    /// Position within the byte, in bit-level mode
+    pub bit: Option<u64>,
+    /// Up to eight bytes, starting at `offset`
+    pub bytes: Vec<u8>,
+}
+
+impl Inspection {
+    fn read<const N: usize>(&self) -> Option<[u8; N]> {
+        self.bytes.get(..N)?.try_into().ok()
+    }
+
+    /// Integer and floating point interpretations as (type, little endian, big endian).
+    pub fn numbers(&self) -> Vec<(&'static str, String, String)> {
+        let mut numbers = vec![];
+        macro_rules! number {
+            ($name:literal, $type:ty) => {
+                if let Some(bytes) = self.read() {
+                    numbers.push((
+                        $name,
+                        <$type>::from_le_bytes(bytes).to_string(),
+                        <$type>::from_be_bytes(bytes).to_string(),
+                    ));
+                }
+            };
+        }
+        number!("u8", u8);
+        number!("i8", i8);
+        number!("u16", u16);
+        number!("i16", i16);
+        number!("u32", u32);
+        number!("i32", i32);
+        number!("u64", u64);
+        number!("i64", i64);
+        number!("f32", f32);
+        number!("f64", f64);
+        numbers
+    }
+
+    pub fn ascii(&self) -> Option<String> {
+        let byte = *self.bytes.first()?;
+        Some(format!("'{}'", byte.escape_ascii()))
+    }
+
+    /// The UTF-8 encoded character that starts at the offset, if any.
+    pub fn utf8(&self) -> Option<String> {
+        (1..=self.bytes.len().min(4))
+            .find_map(|length| std::str::from_utf8(&self.bytes[..length]).ok())
+            .and_then(|s| s.chars().next())
+            .map(|c| format!("'{}' (U+{:04X})", c.escape_debug(), c as u32))
+    }
+
+    pub fn binary(&self) -> Option<String> {
+        self.bytes.first().map(|byte| format!("{:08b}", byte))
+    }
+
+    pub fn hex(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[test]
+fn inspection_decodes_bytes() {
+    let inspection = Inspection {
+        offset: 0,
+        bit: None,
+        bytes: vec![0xc3, 0xa4, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f],
+    };
+
+    let numbers = inspection.numbers();
+    let number = |name| numbers.iter().find(|(n, _, _)| *n == name).unwrap();
+    assert_eq!(number("u8").1, "195");
+    assert_eq!(number("i8").1, "-61");
+    assert_eq!(number("u16").1, "42179");
+    assert_eq!(number("u16").2, "50084");
+    assert_eq!(number("f64").1, "1.0000000000093656");
+
+    assert_eq!(inspection.utf8().unwrap(), "'ä' (U+00E4)");
+    assert_eq!(inspection.ascii().unwrap(), "'\\xc3'");
+    assert_eq!(inspection.binary().unwrap(), "11000011");
+    assert_eq!(inspection.hex(), "c3 a4 00 00 00 00 f0 3f");
+
+    let short = Inspection {
+        offset: 0,
+        bit: None,
+        bytes: vec![0x41, 0x42],
+    };
+    assert_eq!(short.numbers().len(), 4);
+    assert_eq!(short.utf8().unwrap(), "'A' (U+0041)");
+}
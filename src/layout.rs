@@ -0,0 +1,166 @@
+/// Defines how consecutive elements of the view are arranged on the two-dimensional grid.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+    RowMajor,
+    ColumnMajor,
+    /// Square tiles (with the largest power-of-two side length that fits into both the width
+    /// and the height), each filled along a Hilbert curve. The tiles are arranged row by row.
+    Hilbert,
+    /// Like `Hilbert`, but with a Z-order (Morton) curve.
+    ZOrder,
+}
+
+impl Layout {
+    fn tile_size(width: u64, height: u64) -> u64 {
+        1 << (63 - width.min(height).max(1).leading_zeros())
+    }
+
+    /// Map a position on a grid with the given dimensions to an index into the view. Returns
+    /// `None` for positions that do not show any data.
+    pub fn view_index(&self, x: u64, y: u64, width: u64, height: u64) -> Option<u64> {
+        if x >= width {
+            return None;
+        }
+
+        match self {
+            Layout::RowMajor => Some(y * width + x),
+            Layout::ColumnMajor => (y < height).then(|| x * height + y),
+            Layout::Hilbert | Layout::ZOrder => {
+                let size = Self::tile_size(width, height);
+                let tiles_per_row = width / size;
+                if x >= tiles_per_row * size {
+                    return None;
+                }
+
+                let tile = y / size * tiles_per_row + x / size;
+                let (x, y) = (x % size, y % size);
+                let index = match self {
+                    Layout::Hilbert => hilbert_index(size, x, y),
+                    _ => morton_index(x, y),
+                };
+                Some(tile * size * size + index)
+            }
+        }
+    }
+
+    /// The number of view elements that can be shown on a grid with the given dimensions. For
+    /// tiled layouts, only tiles that fit completely are counted; the partial tiles at the bottom
+    /// show data that follows the visible range.
+    pub fn capacity(&self, width: u64, height: u64) -> u64 {
+        match self {
+            Layout::RowMajor | Layout::ColumnMajor => width * height,
+            Layout::Hilbert | Layout::ZOrder => {
+                let size = Self::tile_size(width, height);
+                (height / size) * (width / size) * size * size
+            }
+        }
+    }
+}
+
+/// Position of (x, y) along a Hilbert curve that fills a square of side length `size` (which
+/// needs to be a power of two).
+fn hilbert_index(size: u64, mut x: u64, mut y: u64) -> u64 {
+    let mut index = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = (x & s) > 0;
+        let ry = (y & s) > 0;
+        index += s * s * ((3 * rx as u64) ^ ry as u64);
+
+        // Rotate the quadrant such that the curve is continuous
+        if !ry {
+            if rx {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s /= 2;
+    }
+    index
+}
+
+/// Position of (x, y) along a Z-order curve, i.e. the interleaved bits of both coordinates.
+fn morton_index(x: u64, y: u64) -> u64 {
+    fn spread(mut v: u64) -> u64 {
+        v &= 0xffff_ffff;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        v = (v | (v << 1)) & 0x5555_5555_5555_5555;
+        v
+    }
+    spread(x) | (spread(y) << 1)
+}
+
+#[test]
+fn layout_row_and_column_major() {
+    assert_eq!(Layout::RowMajor.view_index(2, 3, 10, 5), Some(32));
+    assert_eq!(Layout::RowMajor.view_index(10, 3, 10, 5), None);
+    assert_eq!(Layout::ColumnMajor.view_index(2, 3, 10, 5), Some(13));
+    assert_eq!(Layout::ColumnMajor.view_index(2, 5, 10, 5), None);
+}
+
+#[test]
+fn layout_space_filling_curves() {
+    for layout in [Layout::Hilbert, Layout::ZOrder] {
+        // Width 6 results in tiles of size 4x4
+        let mut indices: Vec<u64> = (0..8)
+            .flat_map(|y| (0..4).map(move |x| layout.view_index(x, y, 6, 8).unwrap()))
+            .collect();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..32).collect::<Vec<_>>());
+        assert_eq!(layout.view_index(4, 0, 6, 8), None);
+        assert_eq!(layout.capacity(6, 8), 32);
+        assert_eq!(layout.capacity(6, 7), 16);
+
+        // Tiles of size 4x4 next to each other
+        let mut indices: Vec<u64> = (0..4)
+            .flat_map(|y| (0..8).map(move |x| layout.view_index(x, y, 9, 4).unwrap()))
+            .collect();
+        assert!(indices[..4].iter().all(|&index| index < 16));
+        indices.sort_unstable();
+        assert_eq!(indices, (0..32).collect::<Vec<_>>());
+        assert_eq!(layout.view_index(8, 0, 9, 4), None);
+        assert_eq!(layout.capacity(9, 4), 32);
+    }
+
+    // Consecutive positions on a Hilbert curve are direct neighbors
+    let mut positions = vec![(0, 0); 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            positions[hilbert_index(8, x, y) as usize] = (x as i64, y as i64);
+        }
+    }
+    for pair in positions.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        assert_eq!((x0 - x1).abs() + (y0 - y1).abs(), 1);
+    }
+
+    assert_eq!(morton_index(0b11, 0b00), 0b0101);
+    assert_eq!(morton_index(0b00, 0b11), 0b1010);
+}
+
+#[test]
+fn layout_tiles_fit_into_the_height() {
+    for layout in [Layout::Hilbert, Layout::ZOrder] {
+        // Tiles are limited by the height (8 rows), five of them fit next to each other
+        let (width, height) = (40, 11);
+        let capacity = layout.capacity(width, height);
+        assert_eq!(capacity, 5 * 64);
+
+        let mut shown = vec![false; capacity as usize];
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(index) = layout.view_index(x, y, width, height) {
+                    if index < capacity {
+                        shown[index as usize] = true;
+                    }
+                }
+            }
+        }
+        assert!(shown.iter().all(|&shown| shown));
+    }
+}
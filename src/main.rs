@@ -1,17 +1,35 @@
 use crate::options::CliOptions;
 use clap::Parser;
 
+mod annotations;
 mod binocle;
 mod buffer;
 mod datatype;
+mod decompress;
 mod event_loop;
+mod follow;
 mod gui;
+mod inspector;
+mod layout;
+mod minimap;
 mod options;
+mod period;
+mod pixel_format;
+mod plot;
+mod process;
+mod render;
+mod search;
+mod selection;
+mod session;
 mod settings;
 mod style;
 mod view;
 
 fn main() -> anyhow::Result<()> {
     let options = CliOptions::parse();
-    event_loop::run(options)
+    if options.output.is_some() {
+        render::run(options)
+    } else {
+        event_loop::run(options)
+    }
 }
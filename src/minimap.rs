@@ -0,0 +1,169 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use crate::buffer::Buffer;
+
+/// Number of blocks the buffer is divided into
+const BLOCKS: u64 = 1024;
+const MIN_BLOCK_SIZE: u64 = 64;
+/// Larger blocks are not read completely, but sampled at this many evenly spaced positions
+const SAMPLES_PER_BLOCK: u64 = 16;
+const SAMPLE_SIZE: u64 = 4096;
+/// Results are sent to the GUI in batches of this many blocks
+const BLOCKS_PER_MESSAGE: usize = 16;
+
+/// Per-block entropy of the whole buffer. It is computed by a background thread on a snapshot
+/// of the buffer, such that neither the GUI nor the view's part of a paged buffer is affected.
+/// Dropping the minimap stops the thread.
+#[derive(Default)]
+pub struct Minimap {
+    buffer_length: u64,
+    pub block_size: u64,
+    /// Entropy of each block that has been processed so far, scaled to [0, 1]
+    pub entropy: Vec<f32>,
+    receiver: Option<Receiver<io::Result<Vec<f32>>>>,
+}
+
+impl Minimap {
+    /// Collect the results of the background thread, and start a new one if the buffer has
+    /// changed its length since. Returns `true` if there are new results.
+    pub fn update(&mut self, buffer: &Buffer) -> io::Result<bool> {
+        let mut updated = false;
+        while let Some(receiver) = &self.receiver {
+            match receiver.try_recv() {
+                Ok(entropy) => {
+                    self.entropy.extend(entropy?);
+                    updated = true;
+                }
+                Err(TryRecvError::Empty) => return Ok(updated),
+                Err(TryRecvError::Disconnected) => self.receiver = None,
+            }
+        }
+
+        let length = buffer.len();
+        if length != self.buffer_length {
+            let block_size = ((length + BLOCKS - 1) / BLOCKS).max(MIN_BLOCK_SIZE);
+            if block_size != self.block_size {
+                self.block_size = block_size;
+                self.entropy.clear();
+            } else {
+                // The buffer has grown (or shrunk), so the last block is not valid anymore
+                let complete_blocks = self.buffer_length.min(length) / block_size;
+                self.entropy.truncate(complete_blocks as usize);
+            }
+            self.buffer_length = length;
+            self.start(buffer.snapshot()?);
+        }
+
+        Ok(updated)
+    }
+
+    /// Whether the background thread is still running.
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// Compute the blocks that are still missing in a background thread.
+    fn start(&mut self, buffer: Buffer) {
+        let (sender, receiver) = mpsc::channel();
+        let block_size = self.block_size;
+        let first_block = self.entropy.len() as u64;
+        thread::spawn(move || {
+            if let Err(e) = build(buffer, block_size, first_block, &sender) {
+                let _ = sender.send(Err(e));
+            }
+        });
+        self.receiver = Some(receiver);
+    }
+}
+
+fn build(
+    mut buffer: Buffer,
+    block_size: u64,
+    first_block: u64,
+    sender: &Sender<io::Result<Vec<f32>>>,
+) -> io::Result<()> {
+    let length = buffer.len();
+    let mut entropies = vec![];
+
+    let mut start = first_block * block_size;
+    while start < length {
+        let block_length = block_size.min(length - start);
+        let mut counts = [0u64; 256];
+        for (sample_start, sample_length) in samples(start, block_length) {
+            buffer.prepare(sample_start, sample_length)?;
+            let data_start = (sample_start - buffer.data_offset()) as usize;
+            for &byte in &buffer.data()[data_start..data_start + sample_length as usize] {
+                counts[byte as usize] += 1;
+            }
+        }
+        entropies.push(entropy(&counts));
+        start += block_length;
+
+        let batch_complete = entropies.len() == BLOCKS_PER_MESSAGE || start >= length;
+        if batch_complete && sender.send(Ok(std::mem::take(&mut entropies))).is_err() {
+            // The minimap has been dropped
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// The parts of a block that are read, as (start, length) pairs. Small blocks are read as a
+/// whole.
+fn samples(start: u64, length: u64) -> Vec<(u64, u64)> {
+    if length <= SAMPLES_PER_BLOCK * SAMPLE_SIZE {
+        return vec![(start, length)];
+    }
+    let spacing = (length - SAMPLE_SIZE) / (SAMPLES_PER_BLOCK - 1);
+    (0..SAMPLES_PER_BLOCK)
+        .map(|i| (start + i * spacing, SAMPLE_SIZE))
+        .collect()
+}
+
+/// Shannon entropy of the byte counts, divided by 8 bits.
+fn entropy(counts: &[u64; 256]) -> f32 {
+    let total = counts.iter().sum::<u64>() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    (entropy / 8.0) as f32
+}
+
+#[test]
+fn minimap_covers_whole_buffer() {
+    let mut data = vec![0u8; 64 * 1024];
+    for (i, byte) in data[32 * 1024..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let buffer = Buffer::VecBuffer(std::sync::Arc::new(data));
+
+    let mut minimap = Minimap::default();
+    minimap.update(&buffer).unwrap();
+    while minimap.is_running() {
+        thread::sleep(std::time::Duration::from_millis(1));
+        minimap.update(&buffer).unwrap();
+    }
+    assert!(!minimap.update(&buffer).unwrap());
+
+    assert_eq!(minimap.block_size, 64);
+    assert_eq!(minimap.entropy.len(), 1024);
+    assert_eq!(minimap.entropy[0], 0.0);
+    assert_eq!(minimap.entropy[1023], 0.75);
+}
+
+#[test]
+fn large_blocks_are_sampled() {
+    let sampled = samples(1000, 1 << 20);
+    assert_eq!(sampled.len() as u64, SAMPLES_PER_BLOCK);
+    assert_eq!(sampled[0], (1000, SAMPLE_SIZE));
+    assert_eq!(sampled.last().unwrap().0 + SAMPLE_SIZE, 1000 + (1 << 20));
+    assert_eq!(samples(0, 100), vec![(0, 100)]);
+}
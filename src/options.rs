@@ -1,17 +1,336 @@
+use std::path::PathBuf;
+
 use clap::{ArgEnum, Parser};
 
+use crate::datatype::{BitOrder, Endianness, Signedness};
+use crate::settings::{GuiDatatype, PixelStyle};
+
 #[derive(Parser)]
 #[clap(version, about)]
 pub struct CliOptions {
-    pub filename: String,
+    /// File to visualize. Use '-' to read from stdin (named pipes are supported as well)
+    #[clap(required_unless_present_any = &["pid", "session"])]
+    pub filename: Option<String>,
+
+    /// Inspect the memory of a running process instead of a file
+    #[clap(long, conflicts_with_all = &["filename", "follow"])]
+    pub pid: Option<u32>,
 
     /// Whether to use memory mapping to read the file contents or not
     #[clap(long, arg_enum, default_value = "mmap")]
     pub backing: BackingOption,
+
+    /// Open a view that has been saved to a session file. The input file is taken from the
+    /// session, unless one is given. Other options override the parameters of the session.
+    #[clap(long, value_name = "FILE", conflicts_with = "pid")]
+    pub session: Option<PathBuf>,
+
+    /// Do not decompress gzip, zstd, xz or bzip2 compressed files
+    #[clap(long)]
+    pub no_decompress: bool,
+
+    /// Watch the file for changes and reload it whenever it is modified
+    #[clap(long, conflicts_with = "output")]
+    pub follow: bool,
+
+    /// Render the view to a PNG image instead of opening a window
+    #[clap(short, long, value_name = "PNG")]
+    pub output: Option<String>,
+
+    /// Height of the rendered image in pixels (defaults to the number of rows needed to show the
+    /// rest of the file, but at most the height of the window)
+    #[clap(long, parse(try_from_str = parse_nonzero), requires = "output")]
+    pub height: Option<u32>,
+
+    /// Render the image this many times and print the average time per frame
+    #[clap(long, value_name = "FRAMES", requires = "output")]
+    pub benchmark: Option<u32>,
+
+    /// Width of the grid
+    #[clap(long)]
+    pub width: Option<isize>,
+
+    /// Stride in bytes
+    #[clap(long)]
+    pub stride: Option<isize>,
+
+    /// Start offset into the file (decimal, or hexadecimal with a '0x' prefix)
+    #[clap(long, parse(try_from_str = parse_number))]
+    pub offset: Option<u64>,
+
+    /// Zoom level (1 to 7)
+    #[clap(long)]
+    pub zoom: Option<isize>,
+
+    /// Only load the part of the file starting at this offset. Accepts decimal or hexadecimal
+    /// (with a '0x' prefix) numbers and size suffixes like 'KiB', 'MiB' or 'GiB'
+    #[clap(long, parse(try_from_str = parse_size), value_name = "OFFSET", conflicts_with = "pid")]
+    pub load_offset: Option<u64>,
+
+    /// Only load this many bytes (same format as for '--load-offset')
+    #[clap(long, parse(try_from_str = parse_size), value_name = "LENGTH", conflicts_with = "pid")]
+    pub load_length: Option<u64>,
+
+    /// Pixel style
+    #[clap(long, arg_enum)]
+    pub style: Option<PixelStyleOption>,
+
+    /// Data type for the 'datatype' pixel style. Selects that style, unless '--style' is given
+    #[clap(long, arg_enum)]
+    pub datatype: Option<DatatypeOption>,
+
+    /// Signedness of integer data types
+    #[clap(long, arg_enum)]
+    pub signedness: Option<SignednessOption>,
+
+    /// Byte order of multi-byte data types
+    #[clap(long, arg_enum)]
+    pub endianness: Option<EndiannessOption>,
+
+    /// Values that are mapped to the ends of the color scale for the 'datatype' pixel style,
+    /// e.g. '0:100' or '-1.5:1.5'
+    #[clap(long, parse(try_from_str = parse_range), value_name = "MIN:MAX", allow_hyphen_values = true)]
+    pub value_range: Option<(f32, f32)>,
+
+    /// Show the hex view on startup
+    #[clap(long, conflicts_with = "output")]
+    pub hex_view: bool,
+
+    /// Show one pixel per bit. Width and stride are then given in bits
+    #[clap(long, arg_enum, value_name = "BIT_ORDER")]
+    pub bits: Option<BitOrderOption>,
 }
 
 #[derive(ArgEnum, Copy, Clone)]
 pub enum BackingOption {
     File,
     Mmap,
+    /// Only map the part of the file that is currently being viewed. Used automatically for
+    /// block devices.
+    Paged,
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+pub enum PixelStyleOption {
+    Colorful,
+    Category,
+    Grayscale,
+    Magma,
+    Plasma,
+    Viridis,
+    Rainbow,
+    Turbo,
+    Cubehelix,
+    Entropy,
+    Rgba,
+    Abgr,
+    Rgb,
+    Bgr,
+    Rgb565,
+    Datatype,
+}
+
+impl From<PixelStyleOption> for PixelStyle {
+    fn from(option: PixelStyleOption) -> Self {
+        match option {
+            PixelStyleOption::Colorful => PixelStyle::Colorful,
+            PixelStyleOption::Category => PixelStyle::Category,
+            PixelStyleOption::Grayscale => PixelStyle::Grayscale,
+            PixelStyleOption::Magma => PixelStyle::GradientMagma,
+            PixelStyleOption::Plasma => PixelStyle::GradientPlasma,
+            PixelStyleOption::Viridis => PixelStyle::GradientViridis,
+            PixelStyleOption::Rainbow => PixelStyle::GradientRainbow,
+            PixelStyleOption::Turbo => PixelStyle::GradientTurbo,
+            PixelStyleOption::Cubehelix => PixelStyle::GradientCubehelix,
+            PixelStyleOption::Entropy => PixelStyle::Entropy,
+            PixelStyleOption::Rgba => PixelStyle::Rgba,
+            PixelStyleOption::Abgr => PixelStyle::Abgr,
+            PixelStyleOption::Rgb => PixelStyle::Rgb,
+            PixelStyleOption::Bgr => PixelStyle::Bgr,
+            PixelStyleOption::Rgb565 => PixelStyle::Rgb565,
+            PixelStyleOption::Datatype => PixelStyle::Datatype,
+        }
+    }
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+pub enum BitOrderOption {
+    Msb,
+    Lsb,
+}
+
+impl From<BitOrderOption> for BitOrder {
+    fn from(option: BitOrderOption) -> Self {
+        match option {
+            BitOrderOption::Msb => BitOrder::MsbFirst,
+            BitOrderOption::Lsb => BitOrder::LsbFirst,
+        }
+    }
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+pub enum DatatypeOption {
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+}
+
+impl From<DatatypeOption> for GuiDatatype {
+    fn from(option: DatatypeOption) -> Self {
+        match option {
+            DatatypeOption::Int8 => GuiDatatype::Integer8,
+            DatatypeOption::Int16 => GuiDatatype::Integer16,
+            DatatypeOption::Int32 => GuiDatatype::Integer32,
+            DatatypeOption::Int64 => GuiDatatype::Integer64,
+            DatatypeOption::Float32 => GuiDatatype::Float32,
+            DatatypeOption::Float64 => GuiDatatype::Float64,
+        }
+    }
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+pub enum SignednessOption {
+    Unsigned,
+    Signed,
+}
+
+impl From<SignednessOption> for Signedness {
+    fn from(option: SignednessOption) -> Self {
+        match option {
+            SignednessOption::Unsigned => Signedness::Unsigned,
+            SignednessOption::Signed => Signedness::Signed,
+        }
+    }
+}
+
+#[derive(ArgEnum, Copy, Clone)]
+pub enum EndiannessOption {
+    Little,
+    Big,
+}
+
+impl From<EndiannessOption> for Endianness {
+    fn from(option: EndiannessOption) -> Self {
+        match option {
+            EndiannessOption::Little => Endianness::Little,
+            EndiannessOption::Big => Endianness::Big,
+        }
+    }
+}
+
+/// Parse a positive integer.
+fn parse_nonzero(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(0) => Err("has to be at least 1".into()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a range of values like '0:100' or '-1.5:1.5'.
+fn parse_range(s: &str) -> Result<(f32, f32), String> {
+    let invalid = |reason: &str| format!("invalid range '{}': {}", s, reason);
+
+    let (min, max) = s
+        .split_once(':')
+        .ok_or_else(|| invalid("expected MIN:MAX"))?;
+    let min: f32 = min.trim().parse().map_err(|_| invalid("not a number"))?;
+    let max: f32 = max.trim().parse().map_err(|_| invalid("not a number"))?;
+    if min < max {
+        Ok((min, max))
+    } else {
+        Err(invalid("the minimum has to be smaller than the maximum"))
+    }
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    let result = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+    result.map_err(|e| format!("invalid number '{}': {}", s, e))
+}
+
+/// Parse a number (decimal, or hexadecimal with a '0x' prefix) with an optional size suffix,
+/// e.g. '4096', '0x1000', '4KiB' or '1.5M'.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = |reason: &str| format!("invalid size '{}': {}", s, reason);
+
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).map_err(|e| invalid(&e.to_string()));
+    }
+
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let factor: u64 = match suffix.trim_start() {
+        "" | "B" => 1,
+        "k" | "K" | "KiB" => 1 << 10,
+        "M" | "MiB" => 1 << 20,
+        "G" | "GiB" => 1 << 30,
+        "T" | "TiB" => 1 << 40,
+        "kB" | "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        _ => return Err(invalid("unknown size suffix")),
+    };
+
+    if number.contains('.') {
+        let number: f64 = number.parse().map_err(|_| invalid("not a number"))?;
+        Ok((number * factor as f64) as u64)
+    } else {
+        let number: u64 = number.parse().map_err(|_| invalid("not a number"))?;
+        number
+            .checked_mul(factor)
+            .ok_or_else(|| invalid("number too large"))
+    }
+}
+
+#[test]
+fn parse_number_decimal_and_hex() {
+    assert_eq!(parse_number("0"), Ok(0));
+    assert_eq!(parse_number("4096"), Ok(4096));
+    assert_eq!(parse_number("0x1000"), Ok(4096));
+    assert_eq!(parse_number("0XfF"), Ok(255));
+    assert!(parse_number("0x").is_err());
+    assert!(parse_number("1k").is_err());
+}
+
+#[test]
+fn parse_size_with_suffixes() {
+    assert_eq!(parse_size("0"), Ok(0));
+    assert_eq!(parse_size("4096"), Ok(4096));
+    assert_eq!(parse_size("0x1000"), Ok(4096));
+    assert_eq!(parse_size("0XfF"), Ok(255));
+    assert_eq!(parse_size("4KiB"), Ok(4096));
+    assert_eq!(parse_size("4k"), Ok(4096));
+    assert_eq!(parse_size("4MiB"), Ok(4 * 1024 * 1024));
+    assert_eq!(parse_size("1.5 MiB"), Ok(3 * 512 * 1024));
+    assert_eq!(parse_size("2GB"), Ok(2_000_000_000));
+    assert!(parse_size("0x").is_err());
+    assert!(parse_size("MiB").is_err());
+    assert!(parse_size("4 parsecs").is_err());
+}
+
+#[test]
+fn parse_value_ranges() {
+    assert_eq!(parse_range("0:100"), Ok((0.0, 100.0)));
+    assert_eq!(parse_range("-1.5:1.5"), Ok((-1.5, 1.5)));
+    assert!(parse_range("100").is_err());
+    assert!(parse_range("1:x").is_err());
+    assert!(parse_range("5:5").is_err());
+}
+
+#[test]
+fn parse_nonzero_rejects_zero() {
+    assert_eq!(parse_nonzero("480"), Ok(480));
+    assert!(parse_nonzero("0").is_err());
+    assert!(parse_nonzero("-1").is_err());
 }
@@ -0,0 +1,157 @@
+/// A likely period of the data, with the (normalized) autocorrelation at that lag as score.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Period {
+    pub length: u64,
+    pub score: f32,
+}
+
+/// Peaks below this autocorrelation are not considered to be periods
+const MIN_SCORE: f64 = 0.05;
+
+/// A peak is hidden in favor of one of its divisors if that one is at least this strong
+/// (relative to the peak). Data with period `p` also has periods `2p`, `3p`, ...
+const DIVISOR_RATIO: f64 = 0.8;
+
+/// The most likely periods of the data (at most `count`, strongest first), up to a length of
+/// `max_period` bytes.
+pub fn candidate_periods(data: &[u8], max_period: usize, count: usize) -> Vec<Period> {
+    let max_lag = max_period.min(data.len() / 2);
+    if max_lag < 3 {
+        return vec![];
+    }
+    let r = autocorrelation(data, max_lag);
+
+    let peaks: Vec<usize> = (2..max_lag)
+        .filter(|&k| r[k] > MIN_SCORE && r[k] > r[k - 1] && r[k] >= r[k + 1])
+        .collect();
+
+    let mut periods: Vec<Period> = peaks
+        .iter()
+        .filter(|&&k| {
+            !peaks
+                .iter()
+                .take_while(|&&d| d < k)
+                .any(|&d| k % d == 0 && r[d] >= DIVISOR_RATIO * r[k])
+        })
+        .map(|&k| Period {
+            length: k as u64,
+            score: r[k] as f32,
+        })
+        .collect();
+
+    periods.sort_by(|a, b| b.score.total_cmp(&a.score));
+    periods.truncate(count);
+    periods
+}
+
+/// Autocorrelation of the mean-free data for lags `0..=max_lag`, normalized such that the value
+/// at lag zero is one. Every lag is scaled by the number of overlapping samples, such that
+/// larger lags are not penalized.
+fn autocorrelation(data: &[u8], max_lag: usize) -> Vec<f64> {
+    let n = data.len();
+    let mean = data.iter().map(|&b| b as f64).sum::<f64>() / n as f64;
+
+    // Zero padding to at least twice the length avoids wrap-around of the circular correlation
+    let size = (2 * n).next_power_of_two();
+    let mut re: Vec<f64> = data.iter().map(|&b| b as f64 - mean).collect();
+    re.resize(size, 0.0);
+    let mut im = vec![0.0; size];
+
+    // Wiener-Khinchin: the autocorrelation is the inverse transform of the power spectrum
+    fft(&mut re, &mut im, false);
+    for (re, im) in re.iter_mut().zip(im.iter_mut()) {
+        *re = *re * *re + *im * *im;
+        *im = 0.0;
+    }
+    fft(&mut re, &mut im, true);
+
+    let zero = re[0];
+    (0..=max_lag)
+        .map(|k| {
+            if zero > 0.0 {
+                re[k] / zero * n as f64 / (n - k) as f64
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// In-place iterative radix-2 FFT. The length has to be a power of two. The inverse transform
+/// is not scaled.
+fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut length = 2;
+    while length <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / length as f64;
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).step_by(length) {
+            let (mut u_re, mut u_im) = (1.0, 0.0);
+            for k in 0..length / 2 {
+                let (a, b) = (start + k, start + k + length / 2);
+                let t_re = re[b] * u_re - im[b] * u_im;
+                let t_im = re[b] * u_im + im[b] * u_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+
+                let next_re = u_re * w_re - u_im * w_im;
+                u_im = u_re * w_im + u_im * w_re;
+                u_re = next_re;
+            }
+        }
+        length *= 2;
+    }
+}
+
+#[test]
+fn autocorrelation_matches_direct_computation() {
+    let data: Vec<u8> = (0..300u32).map(|i| (i * i % 97) as u8).collect();
+    let r = autocorrelation(&data, 50);
+
+    let mean = data.iter().map(|&b| b as f64).sum::<f64>() / data.len() as f64;
+    let x: Vec<f64> = data.iter().map(|&b| b as f64 - mean).collect();
+    let direct = |k: usize| (0..x.len() - k).map(|i| x[i] * x[i + k]).sum::<f64>();
+    assert_eq!(r.len(), 51);
+    for (k, value) in r.into_iter().enumerate() {
+        let expected = direct(k) / direct(0) * 300.0 / (300 - k) as f64;
+        assert!((value - expected).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn finds_row_length() {
+    // An "image" with rows of 37 bytes: a gradient plus some noise
+    let mut state = 12345u32;
+    let data: Vec<u8> = (0..37 * 200)
+        .map(|i| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            ((i % 37) * 6) as u8 ^ (state >> 28) as u8
+        })
+        .collect();
+
+    let periods = candidate_periods(&data, 500, 5);
+    assert_eq!(periods[0].length, 37);
+    assert!(periods[0].score > 0.5);
+    assert!(!periods.iter().any(|p| p.length == 74));
+
+    assert!(candidate_periods(&[1, 2, 3], 500, 5).is_empty());
+}
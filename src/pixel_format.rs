@@ -0,0 +1,197 @@
+use crate::datatype::Endianness;
+use crate::style::rgb565_channels;
+
+/// A way to encode the pixels of a raw image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelFormat {
+    Gray8,
+    Rgb,
+    Bgr,
+    Rgba,
+    /// Little-endian 16-bit pixels, see `style::Rgb565`
+    Rgb565,
+    /// 16-bit samples, e.g. from a depth sensor or a high dynamic range camera
+    Gray16(Endianness),
+}
+
+/// A pixel format that might fit the data. `phase` is the number of bytes to skip until the
+/// first complete pixel starts. The score says how much smoother the data is than it would be
+/// without any correlation between neighboring pixels (1 means perfectly smooth).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FormatGuess {
+    pub format: PixelFormat,
+    pub phase: u64,
+    pub score: f32,
+}
+
+impl PixelFormat {
+    pub const ALL: [PixelFormat; 7] = [
+        PixelFormat::Gray8,
+        PixelFormat::Rgb,
+        PixelFormat::Bgr,
+        PixelFormat::Rgba,
+        PixelFormat::Rgb565,
+        PixelFormat::Gray16(Endianness::Little),
+        PixelFormat::Gray16(Endianness::Big),
+    ];
+
+    /// Number of bytes per pixel.
+    pub fn stride(self) -> usize {
+        match self {
+            PixelFormat::Gray8 => 1,
+            PixelFormat::Rgb | PixelFormat::Bgr => 3,
+            PixelFormat::Rgba => 4,
+            PixelFormat::Rgb565 | PixelFormat::Gray16(_) => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PixelFormat::Gray8 => "8-bit gray",
+            PixelFormat::Rgb => "RGB",
+            PixelFormat::Bgr => "BGR",
+            PixelFormat::Rgba => "RGBA",
+            PixelFormat::Rgb565 => "RGB565",
+            PixelFormat::Gray16(Endianness::Little) => "16-bit LE",
+            PixelFormat::Gray16(Endianness::Big) => "16-bit BE",
+        }
+    }
+
+    /// The channels of a pixel, scaled to [0, 1]. Unused channels are zero.
+    fn decode(self, pixel: &[u8]) -> [f32; 4] {
+        let byte = |i: usize| pixel[i] as f32 / 255.0;
+        match self {
+            PixelFormat::Gray8 => [byte(0), 0.0, 0.0, 0.0],
+            // The channel order does not influence the smoothness
+            PixelFormat::Rgb | PixelFormat::Bgr => [byte(0), byte(1), byte(2), 0.0],
+            PixelFormat::Rgba => [byte(0), byte(1), byte(2), byte(3)],
+            PixelFormat::Rgb565 => {
+                let [r, g, b] = rgb565_channels(u16::from_le_bytes([pixel[0], pixel[1]]));
+                [r as f32 / 31.0, g as f32 / 63.0, b as f32 / 31.0, 0.0]
+            }
+            PixelFormat::Gray16(endianness) => {
+                let bytes = [pixel[0], pixel[1]];
+                let value = match endianness {
+                    Endianness::Little => u16::from_le_bytes(bytes),
+                    Endianness::Big => u16::from_be_bytes(bytes),
+                };
+                [value as f32 / 65535.0, 0.0, 0.0, 0.0]
+            }
+        }
+    }
+
+    /// How much smoother the pixels are than unrelated pairs of pixels: one minus the mean
+    /// difference between consecutive pixels, relative to the mean difference between pixels
+    /// half the data apart. Data without any differences between its pixels scores zero.
+    fn smoothness(self, data: &[u8]) -> Option<f32> {
+        let pixels: Vec<[f32; 4]> = data
+            .chunks_exact(self.stride())
+            .map(|pixel| self.decode(pixel))
+            .collect();
+        if pixels.len() < 16 {
+            return None;
+        }
+
+        let difference = |a: &[f32; 4], b: &[f32; 4]| -> f32 {
+            a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum()
+        };
+        let mean_difference = |distance: usize| {
+            let pairs = pixels.len() - distance;
+            let sum: f32 = (0..pairs)
+                .map(|i| difference(&pixels[i], &pixels[i + distance]))
+                .sum();
+            sum / pairs as f32
+        };
+
+        let neighbors = mean_difference(1);
+        let unrelated = mean_difference(pixels.len() / 2);
+        if unrelated > 0.0 {
+            Some(1.0 - neighbors / unrelated)
+        } else {
+            // Constant data looks the same in every format, so it does not favor any of them
+            Some(0.0)
+        }
+    }
+}
+
+/// A later alignment has to be at least this much better than an earlier one to be chosen
+const PHASE_MARGIN: f32 = 0.02;
+
+/// All pixel formats, ranked by how smooth the data looks in them (best first). Each format is
+/// tried with every alignment and the best one is kept. Some candidates cannot be told apart by
+/// smoothness: RGB and BGR always get the same score, and so do the alignments of formats with
+/// several 8-bit channels (they only rotate the channels). Ties are resolved in favor of the
+/// first format and the smallest alignment.
+pub fn rank_formats(data: &[u8]) -> Vec<FormatGuess> {
+    let mut guesses: Vec<FormatGuess> = PixelFormat::ALL
+        .iter()
+        .filter_map(|&format| {
+            (0..format.stride())
+                .filter_map(|phase| {
+                    let score = format.smoothness(data.get(phase..)?)?;
+                    Some(FormatGuess {
+                        format,
+                        phase: phase as u64,
+                        score,
+                    })
+                })
+                .reduce(|best, guess| {
+                    if guess.score > best.score + PHASE_MARGIN {
+                        guess
+                    } else {
+                        best
+                    }
+                })
+        })
+        .collect();
+
+    // The sort is stable, so RGB stays in front of BGR
+    guesses.sort_by(|a, b| b.score.total_cmp(&a.score));
+    guesses
+}
+
+#[test]
+fn recognizes_smooth_images() {
+    let gradient = |x: usize, y: usize| [(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8];
+
+    // An RGBA image with an opaque alpha channel
+    let mut rgba = vec![];
+    for y in 0..32 {
+        for x in 0..64 {
+            rgba.extend_from_slice(&gradient(x, y));
+            rgba.push(255);
+        }
+    }
+    let ranking = rank_formats(&rgba);
+    assert_eq!(ranking[0].format, PixelFormat::Rgba);
+    assert_eq!(ranking[0].phase, 0);
+
+    // The same image as RGB, starting one byte into the data
+    let rgb: Vec<u8> = std::iter::once(0x42)
+        .chain(rgba.chunks(4).flat_map(|pixel| pixel[..3].to_vec()))
+        .collect();
+    let ranking = rank_formats(&rgb);
+    assert_eq!(ranking[0].format, PixelFormat::Rgb);
+    assert_eq!(ranking[1].format, PixelFormat::Bgr);
+    assert_eq!(ranking[0].score, ranking[1].score);
+
+    // A 16-bit big-endian ramp
+    let ramp: Vec<u8> = (0..2048u16)
+        .flat_map(|i| (i.wrapping_mul(29)).to_be_bytes())
+        .collect();
+    let ranking = rank_formats(&ramp);
+    assert_eq!(ranking[0].format, PixelFormat::Gray16(Endianness::Big));
+    assert_eq!(ranking[0].phase, 0);
+
+    assert!(rank_formats(&[1, 2, 3]).is_empty());
+}
+
+#[test]
+fn constant_data_favors_no_format() {
+    let ranking = rank_formats(&[0x80; 256]);
+    assert_eq!(ranking.len(), PixelFormat::ALL.len());
+    assert!(ranking
+        .iter()
+        .all(|guess| guess.score == 0.0 && guess.phase == 0));
+    assert_eq!(ranking[0].format, PixelFormat::Gray8);
+}
@@ -0,0 +1,152 @@
+use crate::style::{rgba_from_color, Color};
+
+const SIZE: usize = 256;
+
+/// Density plot of all consecutive byte pairs (a, b) in a range of data. The first byte of a pair
+/// selects the column, the second one the row.
+pub struct Digraph {
+    counts: Vec<u32>,
+}
+
+impl Digraph {
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut counts = vec![0; SIZE * SIZE];
+        for pair in data.windows(2) {
+            counts[pair[1] as usize * SIZE + pair[0] as usize] += 1;
+        }
+        Digraph { counts }
+    }
+
+    /// Draw the plot into the top left corner of the frame, scaled up by the largest integer
+    /// factor that fits into the given size. Intensities are log-scaled, such that rare pairs
+    /// remain visible.
+    pub fn draw(&self, frame: &mut [u8], frame_width: usize, (width, height): (usize, usize)) {
+        let scale = (width.min(height) / SIZE).max(1);
+
+        let gradient = colorgrad::inferno();
+        let max = self.counts.iter().copied().max().unwrap_or(0);
+        let log_max = (max as f64).ln_1p();
+        let colors: Vec<Color> = self
+            .counts
+            .iter()
+            .map(|&count| {
+                if count == 0 {
+                    [0, 0, 0, 255]
+                } else {
+                    // Start a bit above zero, such that single occurrences differ from empty cells
+                    let intensity = 0.15 + 0.85 * (count as f64).ln_1p() / log_max;
+                    rgba_from_color(gradient.at(intensity))
+                }
+            })
+            .collect();
+
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let (column, row) = (i % frame_width, i / frame_width);
+            let x = column / scale;
+            let y = row / scale;
+
+            let color = if x < SIZE && y < SIZE && column < width && row < height {
+                colors[y * SIZE + x]
+            } else {
+                [0, 0, 0, 0]
+            };
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+/// Point cloud of all distinct consecutive byte triples (a, b, c) in a range of data, shown as
+/// points in a cube.
+pub struct Trigram {
+    /// One bit for each of the 256³ possible triples
+    present: Vec<u64>,
+}
+
+impl Trigram {
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut present = vec![0u64; SIZE * SIZE * SIZE / 64];
+        for triple in data.windows(3) {
+            let index = (triple[0] as usize) << 16 | (triple[1] as usize) << 8 | triple[2] as usize;
+            present[index / 64] |= 1 << (index % 64);
+        }
+        Trigram { present }
+    }
+
+    fn triples(&self) -> impl Iterator<Item = [u8; 3]> + '_ {
+        self.present
+            .iter()
+            .enumerate()
+            .filter(|(_, &bits)| bits != 0)
+            .flat_map(|(word, &bits)| {
+                (0..64)
+                    .filter(move |bit| bits & (1 << bit) != 0)
+                    .map(move |bit| {
+                        let index = word * 64 + bit;
+                        [(index >> 16) as u8, (index >> 8) as u8, index as u8]
+                    })
+            })
+    }
+
+    /// Draw the cloud centered in the top left area of the frame with the given size, rotated
+    /// by `(yaw, pitch)`. Points are colored by their position in the cube and darkened with
+    /// increasing distance from the viewer.
+    pub fn draw(
+        &self,
+        frame: &mut [u8],
+        frame_width: usize,
+        (width, height): (usize, usize),
+        (yaw, pitch): (f32, f32),
+    ) {
+        let frame_height = frame.len() / 4 / frame_width;
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let inside = i % frame_width < width && i / frame_width < height;
+            pixel.copy_from_slice(&[0, 0, 0, if inside { 255 } else { 0 }]);
+        }
+
+        // The rotated cube (with a half diagonal of √3) always fits into the area
+        let scale = width.min(height) as f32 / 2.0 / 3f32.sqrt();
+        let center = (width as f32 / 2.0, height as f32 / 2.0);
+        let (sin_yaw, cos_yaw) = yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = pitch.sin_cos();
+
+        let mut depth = vec![f32::NEG_INFINITY; frame_width * frame_height];
+        for triple in self.triples() {
+            let [x, y, z] = triple.map(|v| v as f32 / 127.5 - 1.0);
+
+            let (x, z) = (x * cos_yaw + z * sin_yaw, z * cos_yaw - x * sin_yaw);
+            let (y, z) = (y * cos_pitch - z * sin_pitch, y * sin_pitch + z * cos_pitch);
+
+            let px = (center.0 + x * scale) as usize;
+            let py = (center.1 - y * scale) as usize;
+            if px >= width || py >= height {
+                continue;
+            }
+
+            let index = py * frame_width + px;
+            if z > depth[index] {
+                depth[index] = z;
+
+                let brightness = 0.4 + 0.6 * (z + 3f32.sqrt()) / (2.0 * 3f32.sqrt());
+                let color = triple.map(|v| ((64.0 + v as f32 * 0.75) * brightness) as u8);
+                frame[index * 4..index * 4 + 3].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+#[test]
+fn digraph_counts_pairs() {
+    let digraph = Digraph::from_bytes(b"abababc");
+    let count = |a: u8, b: u8| digraph.counts[b as usize * SIZE + a as usize];
+    assert_eq!(count(b'a', b'b'), 3);
+    assert_eq!(count(b'b', b'a'), 2);
+    assert_eq!(count(b'b', b'c'), 1);
+    assert_eq!(count(b'c', b'a'), 0);
+}
+
+#[test]
+fn trigram_collects_distinct_triples() {
+    let trigram = Trigram::from_bytes(b"abcabcd");
+    let triples: Vec<[u8; 3]> = trigram.triples().collect();
+    assert_eq!(triples, vec![*b"abc", *b"bca", *b"bcd", *b"cab"]);
+}
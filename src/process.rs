@@ -0,0 +1,132 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+
+pub const PAGE_SIZE: u64 = 4096;
+
+/// A memory region of a process, as listed in `/proc/<pid>/maps`.
+#[derive(Clone)]
+pub struct Mapping {
+    pub start: u64,
+    pub end: u64,
+    pub permissions: String,
+    pub path: String,
+}
+
+impl Mapping {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.permissions.starts_with('r')
+    }
+
+    pub fn label(&self) -> String {
+        format!(
+            "{:012x}-{:012x} {} {}",
+            self.start, self.end, self.permissions, self.path
+        )
+    }
+}
+
+fn parse_mapping(line: &str) -> Option<Mapping> {
+    // Format: "start-end perms offset dev inode [path]"
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let permissions = fields.next()?.to_owned();
+    let path = fields.skip(3).collect::<Vec<_>>().join(" ");
+
+    Some(Mapping {
+        start: u64::from_str_radix(start, 16).ok()?,
+        end: u64::from_str_radix(end, 16).ok()?,
+        permissions,
+        path,
+    })
+}
+
+/// Read access to the memory of a running process via `/proc/<pid>/mem`.
+pub struct ProcessMemory {
+    pid: u32,
+    mem: File,
+    pub mappings: Vec<Mapping>,
+}
+
+impl ProcessMemory {
+    /// Reads are attempted in chunks of this size first, and page by page only if that fails.
+    const CHUNK_SIZE: usize = 1024 * 1024;
+
+    pub fn open(pid: u32) -> io::Result<Self> {
+        let maps = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+        let mappings = maps.lines().filter_map(parse_mapping).collect();
+        let mem = File::open(format!("/proc/{}/mem", pid))?;
+
+        Ok(ProcessMemory { pid, mem, mappings })
+    }
+
+    /// An independent handle to the same memory (with the same list of mappings), such that
+    /// reads from another thread do not interfere.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(ProcessMemory {
+            pid: self.pid,
+            mem: File::open(format!("/proc/{}/mem", self.pid))?,
+            mappings: self.mappings.clone(),
+        })
+    }
+
+    /// The mapping to show initially: the heap if there is one, otherwise the first readable one.
+    pub fn default_mapping(&self) -> Option<usize> {
+        self.mappings
+            .iter()
+            .position(|m| m.path == "[heap]")
+            .or_else(|| self.mappings.iter().position(Mapping::is_readable))
+    }
+
+    /// Read a part of a mapping, starting `offset` bytes into it. Pages that can not be read
+    /// (e.g. guard pages, or device mappings that fail with EIO) are filled with zeros.
+    pub fn read(&mut self, index: usize, offset: u64, data: &mut [u8]) {
+        let mapping = &self.mappings[index];
+        let start = mapping.start + offset;
+        data.fill(0);
+        if !mapping.is_readable() {
+            return;
+        }
+
+        for (i, chunk) in data.chunks_mut(Self::CHUNK_SIZE).enumerate() {
+            let address = start + (i * Self::CHUNK_SIZE) as u64;
+            if self.read_at(address, chunk).is_ok() {
+                continue;
+            }
+            for (page, chunk) in chunk.chunks_mut(PAGE_SIZE as usize).enumerate() {
+                if self
+                    .read_at(address + (page as u64) * PAGE_SIZE, chunk)
+                    .is_err()
+                {
+                    chunk.fill(0);
+                }
+            }
+        }
+    }
+
+    fn read_at(&mut self, address: u64, data: &mut [u8]) -> io::Result<()> {
+        self.mem.seek(SeekFrom::Start(address))?;
+        self.mem.read_exact(data)
+    }
+}
+
+#[test]
+fn parse_maps_line() {
+    let mapping = parse_mapping(
+        "7f1c2a3b4000-7f1c2a3b6000 r-xp 00002000 08:01 1234    /usr/lib/libfoo bar.so",
+    )
+    .unwrap();
+    assert_eq!(mapping.start, 0x7f1c2a3b4000);
+    assert_eq!(mapping.end, 0x7f1c2a3b6000);
+    assert_eq!(mapping.len(), 0x2000);
+    assert_eq!(mapping.permissions, "r-xp");
+    assert_eq!(mapping.path, "/usr/lib/libfoo bar.so");
+    assert!(mapping.is_readable());
+
+    let anonymous = parse_mapping("55d0c0a00000-55d0c0a21000 ---p 00000000 00:00 0").unwrap();
+    assert_eq!(anonymous.path, "");
+    assert!(!anonymous.is_readable());
+}
@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+
+use crate::binocle::Binocle;
+use crate::options::CliOptions;
+use crate::settings::HEIGHT;
+
+/// Render a single frame without opening a window and write it to a PNG file.
+pub fn run(options: CliOptions) -> Result<()> {
+    env_logger::init();
+
+    let output = options.output.clone().expect("output path");
+    let height = options.height;
+    let benchmark = options.benchmark;
+
+    let mut binocle = Binocle::new(options)?;
+    let settings = &mut binocle.settings;
+
+    let zoom_factor = settings.zoom_factor();
+    settings.canvas_width = settings.width * zoom_factor;
+
+    let width = settings.canvas_width as u32;
+    let height = height.unwrap_or_else(|| {
+        let row_length = settings.row_length();
+        let remaining = settings
+            .length_in_units()
+            .saturating_sub(settings.view_offset());
+        let rows = (remaining + row_length - 1) / row_length;
+        (rows * zoom_factor as u64).clamp(1, HEIGHT as u64) as u32
+    });
+
+    binocle.settings.canvas_height = height as isize;
+
+    let mut frame = vec![0; (width * height * 4) as usize];
+    binocle.draw(&mut frame, width as usize);
+
+    if let Some(frames) = benchmark {
+        let start = Instant::now();
+        for _ in 0..frames {
+            binocle.draw(&mut frame, width as usize);
+        }
+        let per_frame = start.elapsed() / frames.max(1);
+        eprintln!(
+            "{}x{} pixels, {} frames: {:.2} ms per frame",
+            width,
+            height,
+            frames,
+            per_frame.as_secs_f64() * 1000.0
+        );
+    }
+
+    let file =
+        File::create(&output).with_context(|| format!("Could not create file '{}'", output))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&frame))
+        .with_context(|| format!("Could not write PNG image to '{}'", output))?;
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use std::io;
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::buffer::Buffer;
+
+/// The buffer is searched in chunks of this size
+const CHUNK_SIZE: u64 = 16 << 20;
+/// Consecutive chunks overlap by this many bytes, such that matches that cross a chunk boundary
+/// are found, as long as they are shorter than this
+const OVERLAP: u64 = 4096;
+/// The search stops after this many matches
+pub const MAX_MATCHES: usize = 100_000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SearchKind {
+    /// Hexadecimal bytes, with '??' as wildcard, e.g. '7f 45 4c 46 ?? 01'
+    Hex,
+    /// A string, encoded as UTF-8 (which includes ASCII)
+    Text,
+    /// A string, encoded as UTF-16 in little or big endian
+    Utf16,
+    /// A regular expression on bytes
+    Regex,
+}
+
+/// Turn a query into a regular expression on bytes.
+pub fn compile(query: &str, kind: SearchKind) -> Result<Regex, String> {
+    if query.is_empty() {
+        return Err("empty query".into());
+    }
+
+    let pattern = match kind {
+        SearchKind::Hex => parse_hex(query)?,
+        SearchKind::Text => escape_bytes(query.as_bytes()),
+        SearchKind::Utf16 => {
+            let little_endian: Vec<u8> = query.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            let big_endian: Vec<u8> = query.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            format!(
+                "{}|{}",
+                escape_bytes(&little_endian),
+                escape_bytes(&big_endian)
+            )
+        }
+        SearchKind::Regex => query.to_owned(),
+    };
+
+    // Match raw bytes. Regular expressions can opt in to Unicode with '(?u)', and only the
+    // wildcards of hex patterns match newlines.
+    RegexBuilder::new(&pattern)
+        .unicode(false)
+        .dot_matches_new_line(kind != SearchKind::Regex)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn escape_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("\\x{:02x}", byte))
+        .collect()
+}
+
+fn parse_hex(query: &str) -> Result<String, String> {
+    let digits: String = query.split_whitespace().collect();
+    if digits.len() % 2 != 0 {
+        return Err("odd number of hex digits".into());
+    }
+
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).map_err(|e| e.to_string())?;
+            if pair == "??" {
+                Ok(".".to_owned())
+            } else {
+                u8::from_str_radix(pair, 16)
+                    .map(|byte| escape_bytes(&[byte]))
+                    .map_err(|_| format!("invalid hex byte '{}'", pair))
+            }
+        })
+        .collect()
+}
+
+pub enum SearchMessage {
+    /// The buffer has been searched up to the given position
+    Progress {
+        searched: u64,
+        matches: Vec<Range<u64>>,
+    },
+    Finished,
+    Failed(io::Error),
+}
+
+/// A search over a whole buffer, running in a background thread. Dropping it cancels the
+/// search.
+pub struct Search {
+    pub receiver: Receiver<SearchMessage>,
+}
+
+impl Search {
+    pub fn start(buffer: Buffer, regex: Regex) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let message = match search(buffer, &regex, &sender) {
+                Ok(()) => SearchMessage::Finished,
+                Err(e) => SearchMessage::Failed(e),
+            };
+            let _ = sender.send(message);
+        });
+        Search { receiver }
+    }
+}
+
+fn search(mut buffer: Buffer, regex: &Regex, sender: &Sender<SearchMessage>) -> io::Result<()> {
+    let length = buffer.len();
+    let mut found = 0;
+    // Matches must not overlap with the previous one, which might reach into this chunk
+    let mut previous_end = 0;
+
+    let mut start = 0;
+    while start < length && found < MAX_MATCHES {
+        let end = (start + CHUNK_SIZE).min(length);
+        let chunk_length = (end + OVERLAP).min(length) - start;
+
+        buffer.prepare(start, chunk_length)?;
+        let data_start = (start - buffer.data_offset()) as usize;
+        let chunk = &buffer.data()[data_start..(data_start + chunk_length as usize)];
+
+        let matches: Vec<Range<u64>> = regex
+            .find_iter(chunk)
+            .map(|m| (start + m.start() as u64)..(start + m.end() as u64))
+            .filter(|m| !m.is_empty() && m.start >= previous_end)
+            .take_while(|m| m.start < end)
+            .take(MAX_MATCHES - found)
+            .collect();
+        found += matches.len();
+        if let Some(last) = matches.last() {
+            previous_end = last.end;
+        }
+
+        let progress = SearchMessage::Progress {
+            searched: end,
+            matches,
+        };
+        if sender.send(progress).is_err() {
+            // The search has been cancelled
+            return Ok(());
+        }
+        start = end;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn compile_queries() {
+    let data = b"\x7fELF\x02\x01 hello h\x00e\x00l\x00l\x00o\x00";
+    let find = |query, kind| -> Vec<usize> {
+        compile(query, kind)
+            .unwrap()
+            .find_iter(data)
+            .map(|m| m.start())
+            .collect()
+    };
+
+    assert_eq!(find("7f 45 4c 46 ?? 01", SearchKind::Hex), vec![0]);
+    assert_eq!(find("7f45", SearchKind::Hex), vec![0]);
+    assert_eq!(find("hello", SearchKind::Text), vec![7]);
+    assert_eq!(find("hello", SearchKind::Utf16), vec![13]);
+    assert_eq!(find("h.?e", SearchKind::Regex), vec![7, 13]);
+
+    let binary = b"\x00\xde\xad\xff\xfe";
+    let find_binary = |query| -> Vec<usize> {
+        compile(query, SearchKind::Regex)
+            .unwrap()
+            .find_iter(binary)
+            .map(|m| m.start())
+            .collect()
+    };
+    assert_eq!(find_binary(r"\xde\xad"), vec![1]);
+    assert_eq!(find_binary(r"\xff"), vec![3]);
+    assert_eq!(find_binary(r"\xff."), vec![3]);
+
+    assert!(compile("7f 4", SearchKind::Hex).is_err());
+    assert!(compile("7f zz", SearchKind::Hex).is_err());
+    assert!(compile("", SearchKind::Text).is_err());
+}
+
+#[test]
+fn search_across_chunks() {
+    let mut data = vec![0u8; (CHUNK_SIZE + 100) as usize];
+    let boundary = CHUNK_SIZE as usize;
+    data[boundary - 2..boundary + 2].copy_from_slice(b"abcd");
+    data[10..14].copy_from_slice(b"abcd");
+
+    let search = Search::start(
+        Buffer::VecBuffer(std::sync::Arc::new(data)),
+        compile("abcd", SearchKind::Text).unwrap(),
+    );
+    let mut matches = vec![];
+    for message in search.receiver.iter() {
+        match message {
+            SearchMessage::Progress { matches: m, .. } => matches.extend(m),
+            SearchMessage::Finished => break,
+            SearchMessage::Failed(e) => panic!("{}", e),
+        }
+    }
+    let boundary = boundary as u64;
+    assert_eq!(matches, vec![10..14, (boundary - 2)..(boundary + 2)]);
+}
@@ -0,0 +1,167 @@
+use std::ops::Range;
+
+/// A selected part of the buffer. Positions are in bytes, relative to the start of the buffer.
+#[derive(Clone, PartialEq)]
+pub enum Selection {
+    /// A contiguous range of bytes
+    Linear { start: u64, end: u64 },
+    /// A rectangle on a row-major grid: `rows` runs of `width` bytes, `row_length` bytes apart
+    Rectangular {
+        start: u64,
+        width: u64,
+        rows: u64,
+        row_length: u64,
+    },
+}
+
+/// Something to do with the selected data, requested through the GUI.
+pub enum SelectionAction {
+    Save,
+    CopyHex,
+    CopyCArray,
+}
+
+impl Selection {
+    /// The first selected byte.
+    pub fn start(&self) -> u64 {
+        match *self {
+            Selection::Linear { start, .. } | Selection::Rectangular { start, .. } => start,
+        }
+    }
+
+    /// The position after the last selected byte.
+    pub fn end(&self) -> u64 {
+        match *self {
+            Selection::Linear { end, .. } => end,
+            Selection::Rectangular {
+                start,
+                width,
+                rows,
+                row_length,
+            } => start + (rows - 1) * row_length + width,
+        }
+    }
+
+    /// Number of selected bytes.
+    pub fn len(&self) -> u64 {
+        match *self {
+            Selection::Linear { start, end } => end - start,
+            Selection::Rectangular { width, rows, .. } => width * rows,
+        }
+    }
+
+    pub fn contains(&self, position: u64) -> bool {
+        match *self {
+            Selection::Linear { start, end } => (start..end).contains(&position),
+            Selection::Rectangular {
+                start,
+                width,
+                rows,
+                row_length,
+            } => {
+                position >= start
+                    && (position - start) / row_length < rows
+                    && (position - start) % row_length < width
+            }
+        }
+    }
+
+    /// The first `length` bytes of the selection (rounded up to whole rows for rectangles).
+    pub fn truncated(&self, length: u64) -> Selection {
+        match *self {
+            Selection::Linear { start, end } => Selection::Linear {
+                start,
+                end: end.min(start + length),
+            },
+            Selection::Rectangular {
+                start,
+                width,
+                rows,
+                row_length,
+            } => Selection::Rectangular {
+                start,
+                width,
+                rows: rows.min((length + width - 1) / width),
+                row_length,
+            },
+        }
+    }
+
+    /// The selected byte ranges, in order.
+    pub fn ranges(&self) -> Vec<Range<u64>> {
+        match *self {
+            Selection::Linear { start, end } => {
+                let range = start..end;
+                vec![range]
+            }
+            Selection::Rectangular {
+                start,
+                width,
+                rows,
+                row_length,
+            } => (0..rows)
+                .map(|row| {
+                    let row_start = start + row * row_length;
+                    row_start..(row_start + width)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Bytes as hexadecimal numbers, 16 per line.
+pub fn format_hex(data: &[u8]) -> String {
+    data.chunks(16)
+        .map(|line| {
+            line.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Bytes as a C array definition, 12 per line.
+pub fn format_c_array(data: &[u8]) -> String {
+    let lines: Vec<String> = data
+        .chunks(12)
+        .map(|line| {
+            let bytes: Vec<String> = line.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+            format!("    {},", bytes.join(", "))
+        })
+        .collect();
+    format!(
+        "unsigned char data[{}] = {{\n{}\n}};\n",
+        data.len(),
+        lines.join("\n")
+    )
+}
+
+#[test]
+fn rectangular_selection() {
+    let selection = Selection::Rectangular {
+        start: 10,
+        width: 3,
+        rows: 2,
+        row_length: 8,
+    };
+    assert_eq!(selection.len(), 6);
+    assert_eq!(selection.end(), 21);
+    assert_eq!(selection.ranges(), vec![10..13, 18..21]);
+    assert!(selection.contains(12));
+    assert!(!selection.contains(13));
+    assert!(selection.contains(18));
+    assert!(!selection.contains(26));
+    assert_eq!(selection.truncated(4).ranges(), vec![10..13, 18..21]);
+    assert_eq!(selection.truncated(3).len(), 3);
+}
+
+#[test]
+fn selection_formats() {
+    assert_eq!(format_hex(&[0x7f, 0x45, 0x4c, 0x46]), "7f 45 4c 46");
+    assert_eq!(
+        format_c_array(&[1, 2, 255]),
+        "unsigned char data[3] = {\n    0x01, 0x02, 0xff,\n};\n"
+    );
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+
+use crate::datatype::{BitOrder, Endianness, Signedness};
+use crate::layout::Layout;
+use crate::settings::{CanvasMode, GuiDatatype, PixelStyle, Settings};
+
+/// Something to do with a session file, requested through the GUI.
+pub enum SessionAction {
+    Save,
+    Open,
+}
+
+/// An input file together with the parameters of a view on it. Sessions are stored as text
+/// files with one `key = value` pair per line:
+///
+/// ```text
+/// input = firmware.bin
+/// width = 320
+/// offset = 0x1f400
+/// pixel_style = rgb
+/// ```
+pub struct Session {
+    /// Path of the input file. Relative paths are relative to the directory of the session file.
+    pub input: PathBuf,
+    parameters: Vec<(String, String)>,
+}
+
+const CANVAS_MODES: &[(&str, CanvasMode)] = &[
+    ("grid", CanvasMode::Grid),
+    ("digraph", CanvasMode::Digraph),
+    ("trigram", CanvasMode::Trigram),
+];
+
+const LAYOUTS: &[(&str, Layout)] = &[
+    ("row-major", Layout::RowMajor),
+    ("column-major", Layout::ColumnMajor),
+    ("hilbert", Layout::Hilbert),
+    ("z-order", Layout::ZOrder),
+];
+
+const BIT_ORDERS: &[(&str, Option<BitOrder>)] = &[
+    ("none", None),
+    ("msb", Some(BitOrder::MsbFirst)),
+    ("lsb", Some(BitOrder::LsbFirst)),
+];
+
+const PIXEL_STYLES: &[(&str, PixelStyle)] = &[
+    ("colorful", PixelStyle::Colorful),
+    ("category", PixelStyle::Category),
+    ("grayscale", PixelStyle::Grayscale),
+    ("magma", PixelStyle::GradientMagma),
+    ("plasma", PixelStyle::GradientPlasma),
+    ("viridis", PixelStyle::GradientViridis),
+    ("rainbow", PixelStyle::GradientRainbow),
+    ("turbo", PixelStyle::GradientTurbo),
+    ("cubehelix", PixelStyle::GradientCubehelix),
+    ("entropy", PixelStyle::Entropy),
+    ("rgba", PixelStyle::Rgba),
+    ("abgr", PixelStyle::Abgr),
+    ("rgb", PixelStyle::Rgb),
+    ("bgr", PixelStyle::Bgr),
+    ("rgb565", PixelStyle::Rgb565),
+    ("datatype", PixelStyle::Datatype),
+];
+
+const DATATYPES: &[(&str, GuiDatatype)] = &[
+    ("int8", GuiDatatype::Integer8),
+    ("int16", GuiDatatype::Integer16),
+    ("int32", GuiDatatype::Integer32),
+    ("int64", GuiDatatype::Integer64),
+    ("float32", GuiDatatype::Float32),
+    ("float64", GuiDatatype::Float64),
+];
+
+const SIGNEDNESSES: &[(&str, Signedness)] = &[
+    ("unsigned", Signedness::Unsigned),
+    ("signed", Signedness::Signed),
+];
+
+const ENDIANNESSES: &[(&str, Endianness)] =
+    &[("little", Endianness::Little), ("big", Endianness::Big)];
+
+fn name_of<T: PartialEq>(table: &[(&'static str, T)], value: &T) -> &'static str {
+    table
+        .iter()
+        .find(|(_, v)| v == value)
+        .map(|(name, _)| *name)
+        .expect("all values have a name")
+}
+
+fn lookup<T: Clone>(table: &[(&str, T)], name: &str) -> Result<T> {
+    table
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, value)| value.clone())
+        .ok_or_else(|| {
+            let names: Vec<&str> = table.iter().map(|(n, _)| *n).collect();
+            anyhow!("expected one of {}", names.join(", "))
+        })
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    Ok(value.parse()?)
+}
+
+impl Session {
+    /// The current view on the given input file. The offset is stored relative to the start of
+    /// the file, such that it does not depend on which part of the file is loaded.
+    pub fn capture(input: &Path, settings: &Settings) -> Self {
+        let datatype = &settings.datatype_settings;
+        let base_units = settings.base_offset * settings.units_per_byte();
+        let (min, max) = settings.value_range;
+        let parameters = [
+            (
+                "canvas_mode",
+                name_of(CANVAS_MODES, &settings.canvas_mode).into(),
+            ),
+            ("zoom", settings.zoom.to_string()),
+            ("layout", name_of(LAYOUTS, &settings.layout).into()),
+            ("bits", name_of(BIT_ORDERS, &settings.bit_order).into()),
+            ("width", settings.width.to_string()),
+            ("offset", format!("0x{:x}", base_units + settings.offset)),
+            ("offset_fine", settings.offset_fine.to_string()),
+            ("stride", settings.stride.to_string()),
+            (
+                "pixel_style",
+                name_of(PIXEL_STYLES, &settings.pixel_style).into(),
+            ),
+            ("entropy_window", settings.entropy_window_size.to_string()),
+            ("datatype", name_of(DATATYPES, &datatype.datatype).into()),
+            (
+                "signedness",
+                name_of(SIGNEDNESSES, &datatype.signedness).into(),
+            ),
+            (
+                "endianness",
+                name_of(ENDIANNESSES, &datatype.endianness).into(),
+            ),
+            ("value_range", format!("{} {}", min, max)),
+        ];
+
+        Session {
+            input: input.to_path_buf(),
+            parameters: parameters
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        }
+    }
+
+    /// Change the settings to show the view of this session.
+    pub fn apply(&self, settings: &mut Settings) -> Result<()> {
+        for (key, value) in &self.parameters {
+            Self::apply_parameter(settings, key, value)
+                .with_context(|| format!("Invalid value '{}' for '{}'", value, key))?;
+        }
+        settings.clamp();
+        Ok(())
+    }
+
+    fn apply_parameter(settings: &mut Settings, key: &str, value: &str) -> Result<()> {
+        let datatype = &mut settings.datatype_settings;
+        match key {
+            "canvas_mode" => settings.canvas_mode = lookup(CANVAS_MODES, value)?,
+            "zoom" => settings.zoom = parse_number(value)?,
+            "layout" => settings.layout = lookup(LAYOUTS, value)?,
+            // Changing the bit order converts the offsets, so it has to happen before they are set
+            "bits" => settings.set_bit_order(lookup(BIT_ORDERS, value)?),
+            "width" => settings.width = parse_number(value)?,
+            "offset" => {
+                let offset = u64::from_str_radix(value.trim_start_matches("0x"), 16)?;
+                let base_units = settings.base_offset * settings.units_per_byte();
+                settings.offset = offset.saturating_sub(base_units);
+            }
+            "offset_fine" => settings.offset_fine = parse_number(value)?,
+            "stride" => settings.stride = parse_number(value)?,
+            "pixel_style" => settings.pixel_style = lookup(PIXEL_STYLES, value)?,
+            "entropy_window" => {
+                settings.entropy_window_size = parse_number::<usize>(value)?.max(1);
+            }
+            "datatype" => datatype.datatype = lookup(DATATYPES, value)?,
+            "signedness" => datatype.signedness = lookup(SIGNEDNESSES, value)?,
+            "endianness" => datatype.endianness = lookup(ENDIANNESSES, value)?,
+            "value_range" => {
+                let (min, max) = value
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("expected two numbers"))?;
+                settings.value_range = (parse_number(min)?, parse_number(max.trim())?);
+            }
+            // Sessions written by newer versions might contain parameters that we do not know
+            _ => warn!("Ignoring unknown session parameter '{}'", key),
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read session file '{}'", path.display()))?;
+        let mut session = Self::parse(&content)
+            .with_context(|| format!("Invalid session file '{}'", path.display()))?;
+        if session.input.is_relative() {
+            if let Some(directory) = path.parent() {
+                session.input = directory.join(&session.input);
+            }
+        }
+        Ok(session)
+    }
+
+    /// Write the session to a file. The input path is stored relative to the session file if
+    /// the input lies in the same directory (or below), such that both can be moved together.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let directory = match path.parent() {
+            Some(directory) if directory != Path::new("") => directory.canonicalize()?,
+            _ => std::env::current_dir()?,
+        };
+        let input = self.input.canonicalize()?;
+        let input = input.strip_prefix(&directory).unwrap_or(&input);
+        fs::write(path, self.format(input))
+    }
+
+    fn format(&self, input: &Path) -> String {
+        let mut content = format!("input = {}\n", input.display());
+        for (key, value) in &self.parameters {
+            content.push_str(&format!("{} = {}\n", key, value));
+        }
+        content
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let mut input = None;
+        let mut parameters = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("line {}: expected 'key = value'", i + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key == "input" {
+                input = Some(PathBuf::from(value));
+            } else {
+                parameters.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        Ok(Session {
+            input: input.ok_or_else(|| anyhow!("missing 'input'"))?,
+            parameters,
+        })
+    }
+}
+
+#[test]
+fn session_roundtrip() {
+    let settings = Settings {
+        width: 320,
+        offset: 0x400,
+        stride: 3,
+        zoom: 2,
+        pixel_style: PixelStyle::Rgb,
+        layout: Layout::Hilbert,
+        base_offset: 0x100,
+        buffer_length: 0x10000,
+        value_range: (-1.5, 2.0),
+        ..Default::default()
+    };
+    let session = Session::capture(Path::new("data.bin"), &settings);
+    let content = session.format(&session.input);
+    assert!(content.starts_with("input = data.bin\n"));
+    assert!(content.contains("offset = 0x500\n"));
+    assert!(content.contains("pixel_style = rgb\n"));
+
+    let parsed = Session::parse(&content).unwrap();
+    assert_eq!(parsed.input, Path::new("data.bin"));
+    let mut restored = Settings {
+        base_offset: 0x100,
+        buffer_length: 0x10000,
+        ..Default::default()
+    };
+    parsed.apply(&mut restored).unwrap();
+    assert!(restored.canvas_state() == settings.canvas_state());
+
+    assert!(Session::parse("width = 10").is_err());
+    let invalid = Session::parse("input = a\npixel_style = sepia").unwrap();
+    assert!(invalid.apply(&mut Settings::default()).is_err());
+}
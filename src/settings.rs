@@ -1,9 +1,20 @@
-use crate::datatype::{Endianness, Signedness};
+use std::ops::Range;
 
+use crate::annotations::Annotation;
+use crate::datatype::{BitOrder, Endianness, Signedness};
+use crate::inspector::Inspection;
+use crate::layout::Layout;
+use crate::period::Period;
+use crate::pixel_format::{FormatGuess, PixelFormat};
+use crate::search::SearchKind;
+use crate::selection::{Selection, SelectionAction};
+use crate::session::SessionAction;
+
+/// Initial size of the window
 pub const WIDTH: u32 = 1366;
 pub const HEIGHT: u32 = 800;
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum PixelStyle {
     Grayscale,
     Colorful,
@@ -19,9 +30,21 @@ pub enum PixelStyle {
     Abgr,
     Rgb,
     Bgr,
+    Rgb565,
     Datatype,
 }
 
+/// What is shown on the canvas.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CanvasMode {
+    /// The bytes of the view, one per grid cell
+    Grid,
+    /// Density plot of consecutive byte pairs within the visible range
+    Digraph,
+    /// Rotatable 3D point cloud of consecutive byte triples within the visible range
+    Trigram,
+}
+
 #[derive(Clone, PartialEq)]
 pub enum GuiDatatype {
     Integer8,
@@ -32,31 +55,153 @@ pub enum GuiDatatype {
     Float64,
 }
 
+#[derive(Clone, PartialEq)]
 pub struct DatatypeSettings {
     pub datatype: GuiDatatype,
     pub signedness: Signedness,
     pub endianness: Endianness,
 }
 
+/// Everything that influences the contents of the canvas. Comparing it to the state of the last
+/// frame tells whether the canvas needs to be redrawn.
+#[derive(Clone, PartialEq)]
+pub struct CanvasState {
+    canvas_mode: CanvasMode,
+    trigram_rotation: (f32, f32),
+    zoom: isize,
+    layout: Layout,
+    bit_order: Option<BitOrder>,
+    width: isize,
+    offset: u64,
+    offset_fine: u64,
+    stride: isize,
+    pixel_style: PixelStyle,
+    entropy_window_size: usize,
+    datatype_settings: DatatypeSettings,
+    value_range: (f32, f32),
+    canvas_width: isize,
+    canvas_height: isize,
+    highlight_changes: bool,
+    highlighted_byte: Option<u8>,
+    selection: Option<Selection>,
+    search_version: u64,
+    annotations_version: u64,
+}
+
 pub struct Settings {
+    pub canvas_mode: CanvasMode,
+    /// Rotation of the trigram cloud around the vertical and horizontal axis (in radians)
+    pub trigram_rotation: (f32, f32),
+
     pub zoom: isize,
     pub zoom_range: (isize, isize),
 
+    pub layout: Layout,
+    /// If set, every pixel represents a single bit. Width, stride and offsets are then given
+    /// in bits instead of bytes.
+    pub bit_order: Option<BitOrder>,
+
     pub width: isize,
-    pub offset: isize,
-    pub offset_fine: isize,
+    pub offset: u64,
+    pub offset_fine: u64,
 
     pub stride: isize,
     pub max_stride: isize,
 
+    /// Set by the GUI to guess the pixel format of the selection
+    pub format_detection_requested: bool,
+    /// Pixel formats that might fit the selection, best first
+    pub format_guesses: Vec<FormatGuess>,
+
+    /// Set by the GUI to look for periods in the visible data
+    pub auto_width_requested: bool,
+    /// Likely widths (in elements), strongest first
+    pub width_candidates: Vec<Period>,
+
     pub pixel_style: PixelStyle,
+    /// Number of bytes over which the entropy of each pixel is computed
+    pub entropy_window_size: usize,
     pub datatype_settings: DatatypeSettings,
 
-    pub buffer_length: isize,
+    pub buffer_length: u64,
+
+    /// Size of the part of the window in which the canvas is shown (in physical pixels)
     pub canvas_width: isize,
+    pub canvas_height: isize,
+
+    /// Position of the start of the buffer within the file
+    pub base_offset: u64,
+
+    pub compression: Option<&'static str>,
+    pub compressed_length: u64,
+    /// Set if the file looked compressed but could not be decompressed (and is shown raw)
+    pub decompression_error: Option<String>,
+
+    /// Whether new data can still arrive, i.e. for streams that have not ended yet and for
+    /// followed files
+    pub live_input: bool,
+    pub follow_tail: bool,
+    pub highlight_changes: bool,
+
+    pub mappings: Vec<String>,
+    pub selected_mapping: Option<usize>,
 
     pub value_range: (f32, f32),
 
+    /// Number of bytes covered by the grid
+    pub visible_length: u64,
+
+    /// Entropy of each block of the whole buffer, as far as it has been computed
+    pub minimap: Vec<f32>,
+    pub minimap_block_size: u64,
+
+    pub selection: Option<Selection>,
+    pub selection_action: Option<SelectionAction>,
+    /// Where the selection is saved to
+    pub export_path: String,
+    /// Result of the last selection action
+    pub selection_status: String,
+    /// Text that should be copied to the clipboard
+    pub clipboard: Option<String>,
+
+    pub search_query: String,
+    pub search_kind: SearchKind,
+    /// Set by the GUI to start a new search
+    pub search_requested: bool,
+    pub search_status: String,
+    /// Sorted, non-overlapping byte ranges of all matches found so far
+    pub search_matches: Vec<Range<u64>>,
+    /// Incremented whenever `search_matches` change
+    pub search_version: u64,
+    /// The match that has been navigated to last
+    pub search_current: Option<usize>,
+
+    /// Bookmarks and labeled regions, in the order in which they have been added
+    pub annotations: Vec<Annotation>,
+    /// Incremented whenever `annotations` change, such that they are redrawn and saved
+    pub annotations_version: u64,
+    /// Label and color for the next annotation
+    pub annotation_label: String,
+    pub annotation_color: [u8; 3],
+    /// Result of the last attempt to save the annotations
+    pub annotations_status: String,
+
+    /// Where the session is saved to or opened from
+    pub session_path: String,
+    pub session_action: Option<SessionAction>,
+    /// Result of the last session action
+    pub session_status: String,
+
+    /// Position (in units) of the element under the mouse cursor
+    pub hovered_position: Option<u64>,
+    pub inspection: Option<Inspection>,
+
+    /// Number of occurrences of each byte value within the visible range
+    pub histogram: [u64; 256],
+    pub histogram_log_scale: bool,
+    /// Occurrences of this byte value are highlighted on the canvas
+    pub highlighted_byte: Option<u8>,
+
     pub hex_view_visible: bool,
     pub hex_view: String,
     pub hex_ascii: String,
@@ -66,16 +211,288 @@ pub struct Settings {
 }
 
 impl Settings {
+    pub fn canvas_state(&self) -> CanvasState {
+        CanvasState {
+            canvas_mode: self.canvas_mode,
+            trigram_rotation: self.trigram_rotation,
+            zoom: self.zoom,
+            layout: self.layout,
+            bit_order: self.bit_order,
+            width: self.width,
+            offset: self.offset,
+            offset_fine: self.offset_fine,
+            stride: self.stride,
+            pixel_style: self.pixel_style.clone(),
+            entropy_window_size: self.entropy_window_size,
+            datatype_settings: self.datatype_settings.clone(),
+            value_range: self.value_range,
+            canvas_width: self.canvas_width,
+            canvas_height: self.canvas_height,
+            highlight_changes: self.highlight_changes,
+            highlighted_byte: self.highlighted_byte,
+            selection: self.selection.clone(),
+            search_version: self.search_version,
+            annotations_version: self.annotations_version,
+        }
+    }
+
     pub fn zoom_factor(&self) -> isize {
         2isize.pow((self.zoom - 1) as u32)
     }
 
-    pub fn max_offset_fine(&self) -> isize {
-        3 * self.width * self.stride
+    /// Number of addressable units (bits or bytes) per byte.
+    pub fn units_per_byte(&self) -> u64 {
+        if self.bit_order.is_some() {
+            8
+        } else {
+            1
+        }
+    }
+
+    /// Length of the buffer in units (bits or bytes).
+    pub fn length_in_units(&self) -> u64 {
+        self.buffer_length * self.units_per_byte()
+    }
+
+    /// Switch between byte and bit-level mode, keeping the position in the file.
+    pub fn set_bit_order(&mut self, bit_order: Option<BitOrder>) {
+        match (self.bit_order.is_some(), bit_order.is_some()) {
+            (false, true) => {
+                self.offset *= 8;
+                self.offset_fine *= 8;
+            }
+            (true, false) => {
+                self.offset /= 8;
+                self.offset_fine /= 8;
+            }
+            _ => {}
+        }
+        self.bit_order = bit_order;
+    }
+
+    /// Number of units (bits or bytes) covered by one row of the grid.
+    pub fn row_length(&self) -> u64 {
+        (self.width * self.stride) as u64
+    }
+
+    /// The position in the buffer (in units) at which the view starts.
+    pub fn view_offset(&self) -> u64 {
+        self.offset + self.offset_fine
+    }
+
+    /// The position in the buffer (in bytes) at which the view starts.
+    pub fn view_byte_offset(&self) -> u64 {
+        self.view_offset() / self.units_per_byte()
+    }
+
+    pub fn max_offset_fine(&self) -> u64 {
+        3 * self.row_length()
     }
 
     pub fn max_width(&self) -> isize {
-        2 * (WIDTH as isize)
+        2 * self.canvas_width.max(WIDTH as isize)
+    }
+
+    /// Number of grid rows that fit on the canvas.
+    pub fn grid_height(&self) -> u64 {
+        let zoom_factor = self.zoom_factor() as u64;
+        (self.canvas_height.max(0) as u64 + zoom_factor - 1) / zoom_factor
+    }
+
+    /// The position in the buffer (in units) that is shown at the given pixel of the canvas.
+    /// This is the inverse of the mapping in `Binocle::draw`.
+    pub fn position_at(&self, x: u64, y: u64) -> Option<u64> {
+        if self.canvas_mode != CanvasMode::Grid
+            || x >= self.canvas_width.max(0) as u64
+            || y >= self.canvas_height.max(0) as u64
+        {
+            return None;
+        }
+
+        let zoom_factor = self.zoom_factor() as u64;
+        let view_index = self.layout.view_index(
+            x / zoom_factor,
+            y / zoom_factor,
+            self.width as u64,
+            self.grid_height(),
+        )?;
+        let position = self.view_offset() + view_index * self.stride as u64;
+        (position < self.length_in_units()).then_some(position)
+    }
+
+    /// Move the view such that the given byte position is shown in the first row.
+    pub fn scroll_to(&mut self, position: u64) {
+        let position = position * self.units_per_byte();
+        self.offset = position - position % self.row_length();
+        self.offset_fine = 0;
+    }
+
+    /// Go to the search match with the given index.
+    pub fn go_to_match(&mut self, index: usize) {
+        if let Some(found) = self.search_matches.get(index) {
+            self.search_current = Some(index);
+            self.scroll_to(found.start);
+        }
+    }
+
+    /// Go to the next (or previous) search match, relative to the current one or, if there is
+    /// none, to the view.
+    pub fn go_to_next_match(&mut self, forward: bool) {
+        let count = self.search_matches.len();
+        if count == 0 {
+            return;
+        }
+
+        let index = match self.search_current {
+            Some(current) if forward => (current + 1) % count,
+            Some(current) => (current + count - 1) % count,
+            None => {
+                let view_start = self.view_byte_offset();
+                let next = self
+                    .search_matches
+                    .partition_point(|found| found.start < view_start);
+                if forward {
+                    next % count
+                } else {
+                    (next + count - 1) % count
+                }
+            }
+        };
+        self.go_to_match(index);
+    }
+
+    /// Show the selection in the given pixel format: set the stride and style accordingly and
+    /// start the view at the first complete pixel. The row length is kept (as far as possible).
+    pub fn apply_pixel_format(&mut self, guess: FormatGuess) {
+        let start = match &self.selection {
+            Some(selection) => selection.start(),
+            None => return,
+        };
+        self.set_bit_order(None);
+
+        let stride = guess.format.stride() as isize;
+        self.width = (self.row_length() as isize / stride).max(1);
+        self.stride = stride;
+        self.pixel_style = match guess.format {
+            PixelFormat::Gray8 => PixelStyle::Grayscale,
+            PixelFormat::Rgb => PixelStyle::Rgb,
+            PixelFormat::Bgr => PixelStyle::Bgr,
+            PixelFormat::Rgba => PixelStyle::Rgba,
+            PixelFormat::Rgb565 => PixelStyle::Rgb565,
+            PixelFormat::Gray16(endianness) => {
+                self.datatype_settings = DatatypeSettings {
+                    datatype: GuiDatatype::Integer16,
+                    signedness: Signedness::Unsigned,
+                    endianness,
+                };
+                self.value_range = (0.0, u16::MAX as f32);
+                PixelStyle::Datatype
+            }
+        };
+        self.offset = start + guess.phase;
+        self.offset_fine = 0;
+        self.clamp();
+    }
+
+    /// Bookmark the first byte that is currently shown.
+    pub fn add_bookmark(&mut self) {
+        let start = self.base_offset + self.view_byte_offset();
+        self.add_annotation(start, 0);
+    }
+
+    /// Turn the selection into a labeled region. Rectangular selections are annotated with
+    /// the range that they span.
+    pub fn annotate_selection(&mut self) {
+        if let Some(selection) = &self.selection {
+            let (start, end) = (selection.start(), selection.end());
+            self.add_annotation(self.base_offset + start, end - start);
+        }
+    }
+
+    fn add_annotation(&mut self, start: u64, length: u64) {
+        self.annotations.push(Annotation {
+            start,
+            length,
+            label: std::mem::take(&mut self.annotation_label),
+            color: self.annotation_color,
+        });
+        self.annotations_version += 1;
+    }
+
+    /// Show the start of the annotation with the given index, if it lies within the buffer.
+    pub fn go_to_annotation(&mut self, index: usize) {
+        if let Some(start) = self
+            .annotations
+            .get(index)
+            .and_then(|annotation| annotation.start.checked_sub(self.base_offset))
+            .filter(|&start| start < self.buffer_length)
+        {
+            self.scroll_to(start);
+        }
+    }
+
+    /// The selection spanned by two pixels of the canvas. Rectangular selections need the
+    /// row-major layout in byte mode, otherwise the selection is linear.
+    pub fn selection_between(
+        &self,
+        from: (u64, u64),
+        to: (u64, u64),
+        rectangular: bool,
+    ) -> Option<Selection> {
+        let stride = self.stride as u64;
+
+        if rectangular
+            && self.canvas_mode == CanvasMode::Grid
+            && self.layout == Layout::RowMajor
+            && self.bit_order.is_none()
+        {
+            let zoom_factor = self.zoom_factor() as u64;
+            let last_column = self.width as u64 - 1;
+            let (x0, x1) = (from.0 / zoom_factor, to.0 / zoom_factor);
+            let (y0, y1) = (from.1 / zoom_factor, to.1 / zoom_factor);
+            let (left, right) = (x0.min(x1).min(last_column), x0.max(x1).min(last_column));
+            let (top, bottom) = (y0.min(y1), y0.max(y1));
+
+            let start = self.view_offset() + (top * self.width as u64 + left) * stride;
+            return (start < self.buffer_length).then_some(Selection::Rectangular {
+                start,
+                width: (right - left + 1) * stride,
+                rows: bottom - top + 1,
+                row_length: self.row_length(),
+            });
+        }
+
+        let a = self.position_at(from.0, from.1)?;
+        let b = self.position_at(to.0, to.1)?;
+        let units_per_byte = self.units_per_byte();
+        let first = a.min(b) / units_per_byte;
+        let last = (a.max(b) + stride + units_per_byte - 1) / units_per_byte;
+        Some(Selection::Linear {
+            start: first,
+            end: last.min(self.buffer_length),
+        })
+    }
+
+    /// The offset at which the end of the file is shown at the bottom of the canvas.
+    pub fn end_offset(&self) -> u64 {
+        self.length_in_units()
+            .saturating_sub(self.row_length() * self.grid_height())
+    }
+
+    /// Bring all parameters back into their valid ranges.
+    pub fn clamp(&mut self) {
+        self.zoom = self.zoom.max(self.zoom_range.0);
+        self.zoom = self.zoom.min(self.zoom_range.1);
+
+        self.width = self.width.max(1);
+        self.width = self.width.min(self.max_width());
+
+        self.offset = self.offset.min(self.length_in_units());
+
+        self.offset_fine = self.offset_fine.min(self.max_offset_fine());
+
+        self.stride = self.stride.max(1);
+        self.stride = self.stride.min(self.max_stride);
     }
 }
 
@@ -84,12 +501,21 @@ impl Default for Settings {
         Self {
             zoom: 1,
             zoom_range: (1, 7),
+            canvas_mode: CanvasMode::Grid,
+            trigram_rotation: (0.6, 0.4),
+            layout: Layout::RowMajor,
+            bit_order: None,
             width: 1024,
             offset: 0,
             offset_fine: 0,
             stride: 1,
             max_stride: 128,
+            format_detection_requested: false,
+            format_guesses: vec![],
+            auto_width_requested: false,
+            width_candidates: vec![],
             pixel_style: PixelStyle::Colorful,
+            entropy_window_size: 32,
             datatype_settings: DatatypeSettings {
                 datatype: GuiDatatype::Integer16,
                 signedness: Signedness::Unsigned,
@@ -97,7 +523,45 @@ impl Default for Settings {
             },
             buffer_length: 0,
             canvas_width: WIDTH as isize,
+            canvas_height: HEIGHT as isize,
+            base_offset: 0,
+            compression: None,
+            decompression_error: None,
+            compressed_length: 0,
+            live_input: false,
+            follow_tail: false,
+            highlight_changes: true,
+            mappings: vec![],
+            selected_mapping: None,
             value_range: (0.0, 100.0),
+            visible_length: 0,
+            minimap: vec![],
+            minimap_block_size: 0,
+            selection: None,
+            selection_action: None,
+            export_path: String::new(),
+            selection_status: String::new(),
+            clipboard: None,
+            search_query: String::new(),
+            search_kind: SearchKind::Hex,
+            search_requested: false,
+            search_status: String::new(),
+            search_matches: vec![],
+            search_version: 0,
+            search_current: None,
+            annotations: vec![],
+            annotations_version: 0,
+            annotation_label: String::new(),
+            annotation_color: [255, 160, 0],
+            annotations_status: String::new(),
+            session_path: String::new(),
+            session_action: None,
+            session_status: String::new(),
+            hovered_position: None,
+            inspection: None,
+            histogram: [0; 256],
+            histogram_log_scale: true,
+            highlighted_byte: None,
             hex_view_visible: false,
             hex_view: "".into(),
             hex_ascii: "".into(),
@@ -106,3 +570,55 @@ impl Default for Settings {
         }
     }
 }
+
+#[test]
+fn selection_between_pixels() {
+    let settings = Settings {
+        width: 10,
+        stride: 2,
+        offset: 100,
+        buffer_length: 1000,
+        ..Default::default()
+    };
+    assert!(
+        settings.selection_between((3, 2), (1, 1), false)
+            == Some(Selection::Linear {
+                start: 122,
+                end: 148,
+            })
+    );
+    assert!(
+        settings.selection_between((3, 2), (1, 1), true)
+            == Some(Selection::Rectangular {
+                start: 122,
+                width: 6,
+                rows: 2,
+                row_length: 20,
+            })
+    );
+}
+
+#[test]
+fn position_at_inverts_the_grid_mapping() {
+    let mut settings = Settings {
+        width: 10,
+        stride: 2,
+        offset: 100,
+        buffer_length: 1000,
+        ..Default::default()
+    };
+    assert_eq!(settings.position_at(3, 2), Some(146));
+    assert_eq!(settings.position_at(10, 2), None);
+
+    settings.zoom = 2;
+    assert_eq!(settings.position_at(7, 5), Some(146));
+
+    settings.layout = Layout::ColumnMajor;
+    settings.zoom = 1;
+    settings.canvas_height = 50;
+    assert_eq!(settings.position_at(3, 2), Some(100 + (3 * 50 + 2) * 2));
+
+    settings.offset = 990;
+    assert_eq!(settings.position_at(0, 4), Some(998));
+    assert_eq!(settings.position_at(0, 5), None);
+}
@@ -1,11 +1,11 @@
 use std::convert::TryInto;
 
-use crate::datatype::{Datatype, Endianness};
+use crate::datatype::{BitOrder, Datatype, Endianness};
 use crate::view::View;
 
 pub type Color = [u8; 4];
 
-fn rgba_from_color(color: colorgrad::Color) -> Color {
+pub fn rgba_from_color(color: colorgrad::Color) -> Color {
     [
         (color.r * 255.0) as u8,
         (color.g * 255.0) as u8,
@@ -14,15 +14,37 @@ fn rgba_from_color(color: colorgrad::Color) -> Color {
     ]
 }
 
-pub trait Style {
+/// Styles are shared between all rendering threads. State that changes from pixel to pixel
+/// lives in a `Scratch`, of which every thread has its own.
+pub trait Style: Sync {
     fn init(&mut self, _view: &View) {}
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color;
+    fn color_at_index(&self, view: &View, view_index: u64, scratch: &mut Scratch) -> Color;
+}
+
+/// Per-thread state that styles can use while rendering.
+pub struct Scratch {
+    /// Byte counts of the current entropy window
+    counts: [usize; 256],
+    /// Sum of `c * log2(c)` over all counts `c` of the current entropy window
+    weighted_log_sum: f64,
+    /// The view index of the window that `counts` belongs to
+    current: Option<u64>,
+}
+
+impl Default for Scratch {
+    fn default() -> Self {
+        Scratch {
+            counts: [0; 256],
+            weighted_log_sum: 0.0,
+            current: None,
+        }
+    }
 }
 
 pub struct Colorful;
 
 impl Style for Colorful {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some(b) = view.byte_at(view_index) {
             [b, b.overflowing_mul(2).0, b.overflowing_mul(4).0, 255]
         } else {
@@ -33,7 +55,7 @@ impl Style for Colorful {
 pub struct Grayscale;
 
 impl Style for Grayscale {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some(b) = view.byte_at(view_index) {
             [b, b, b, 255]
         } else {
@@ -45,7 +67,7 @@ impl Style for Grayscale {
 pub struct Category;
 
 impl Style for Category {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some(b) = view.byte_at(view_index) {
             if b == 0x00 {
                 [0, 0, 0, 255]
@@ -85,7 +107,7 @@ impl ColorGradient {
 }
 
 impl Style for ColorGradient {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some(b) = view.byte_at(view_index) {
             self.byte_color[b as usize]
         } else {
@@ -104,8 +126,7 @@ pub struct DatatypeStyle {
 impl DatatypeStyle {
     pub fn new(datatype: Datatype, endianness: Endianness, range: (f32, f32)) -> Self {
         let num_colors = 1024;
-        let mut colors = Vec::new();
-        colors.reserve(num_colors);
+        let mut colors = Vec::with_capacity(num_colors);
 
         let gradient = colorgrad::plasma();
         for i in 0..num_colors {
@@ -136,7 +157,7 @@ impl DatatypeStyle {
 }
 
 impl Style for DatatypeStyle {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         view.slice_at(view_index, self.datatype.size())
             .and_then(|slice| self.datatype.read_as_float_from(slice, self.endianness))
             .map(|t| {
@@ -150,7 +171,7 @@ impl Style for DatatypeStyle {
 pub struct Rgba;
 
 impl Style for Rgba {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some(int) = view.be_u32_at(view_index) {
             int.to_be_bytes()
         } else {
@@ -162,7 +183,7 @@ impl Style for Rgba {
 pub struct Abgr;
 
 impl Style for Abgr {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some(int) = view.be_u32_at(view_index) {
             int.to_le_bytes()
         } else {
@@ -174,7 +195,7 @@ impl Style for Abgr {
 pub struct Rgb;
 
 impl Style for Rgb {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some([r, g, b]) = view.rgb_at(view_index) {
             [r, g, b, 255]
         } else {
@@ -186,7 +207,7 @@ impl Style for Rgb {
 pub struct Bgr;
 
 impl Style for Bgr {
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
         if let Some([b, g, r]) = view.rgb_at(view_index) {
             [r, g, b, 255]
         } else {
@@ -195,10 +216,52 @@ impl Style for Bgr {
     }
 }
 
+/// 16-bit little-endian pixels with 5 bits of red, 6 bits of green and 5 bits of blue.
+pub struct Rgb565;
+
+impl Style for Rgb565 {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
+        if let Some(&[low, high]) = view.slice_at(view_index, 2) {
+            let [r, g, b] = rgb565_channels(u16::from_le_bytes([low, high]));
+            // Replicate the most significant bits, such that the full range is used
+            [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+        } else {
+            [0, 0, 0, 0]
+        }
+    }
+}
+
+/// The red (5 bit), green (6 bit) and blue (5 bit) channels of an RGB565 value.
+pub fn rgb565_channels(value: u16) -> [u8; 3] {
+    [
+        (value >> 11) as u8,
+        (value >> 5) as u8 & 0x3f,
+        value as u8 & 0x1f,
+    ]
+}
+
+/// One pixel per bit: white for set bits, black for unset ones.
+pub struct Bits {
+    pub order: BitOrder,
+}
+
+impl Style for Bits {
+    fn color_at_index(&self, view: &View, view_index: u64, _: &mut Scratch) -> Color {
+        match view.bit_at(view_index, self.order) {
+            Some(true) => [255, 255, 255, 255],
+            Some(false) => [0, 0, 0, 255],
+            None => [0, 0, 0, 0],
+        }
+    }
+}
+
+/// Shannon entropy of the bytes in a window starting at each view element. Consecutive view
+/// elements are handled by sliding the window, so the bytes are only counted once per row. Other
+/// layouts than row-major (and zoomed rows) still count the whole window for most pixels.
 pub struct Entropy {
     window_size: usize,
-    window_size_f64: f64,
-    counts: [i32; 256],
+    /// `c * log2(c)` for all possible counts `c`
+    weighted_log: Vec<f64>,
     /// Cache the gradient color
     byte_color: [Color; 256],
 }
@@ -212,40 +275,105 @@ impl Entropy {
             *color = rgba_from_color(gradient_color);
         }
 
+        let weighted_log = (0..=window_size)
+            .map(|count| match count {
+                0 => 0.0,
+                _ => count as f64 * (count as f64).log2(),
+            })
+            .collect();
+
         Entropy {
             window_size,
-            window_size_f64: window_size as f64,
-            counts: [0; 256],
+            weighted_log,
             byte_color,
         }
     }
-}
 
-impl Style for Entropy {
-    fn init(&mut self, _: &View) {}
+    fn add(&self, scratch: &mut Scratch, byte: u8) {
+        let count = &mut scratch.counts[byte as usize];
+        scratch.weighted_log_sum -= self.weighted_log[*count];
+        *count += 1;
+        scratch.weighted_log_sum += self.weighted_log[*count];
+    }
 
-    fn color_at_index(&mut self, view: &View, view_index: isize) -> Color {
-        if let Some(bytes) = view.slice_at(view_index, self.window_size) {
-            self.counts.fill(0);
+    fn remove(&self, scratch: &mut Scratch, byte: u8) {
+        let count = &mut scratch.counts[byte as usize];
+        scratch.weighted_log_sum -= self.weighted_log[*count];
+        *count -= 1;
+        scratch.weighted_log_sum += self.weighted_log[*count];
+    }
 
-            for byte in bytes.iter() {
-                self.counts[*byte as usize] += 1;
+    /// Move the window to the given view index. Returns `false` if the window does not fit
+    /// into the data.
+    fn move_to(&self, view: &View, view_index: u64, scratch: &mut Scratch) -> bool {
+        let stride = view.stride() as usize;
+        match scratch.current {
+            Some(current) if current == view_index => return true,
+            Some(current) if current + 1 == view_index && stride < self.window_size => {
+                if let Some(bytes) = view.slice_at(current, self.window_size + stride) {
+                    for &byte in &bytes[..stride] {
+                        self.remove(scratch, byte);
+                    }
+                    for &byte in &bytes[self.window_size..] {
+                        self.add(scratch, byte);
+                    }
+                    scratch.current = Some(view_index);
+                    return true;
+                }
             }
+            _ => {}
+        }
 
-            let mut entropy = 0.0f64;
-            for count in self.counts {
-                if count > 0 {
-                    let p = (count as f64) / self.window_size_f64;
-                    entropy -= p * p.log2();
-                }
+        scratch.current = None;
+        if let Some(bytes) = view.slice_at(view_index, self.window_size) {
+            scratch.counts.fill(0);
+            scratch.weighted_log_sum = 0.0;
+            for &byte in bytes {
+                self.add(scratch, byte);
             }
-            entropy *= 1.0f64 / 8.0f64;
+            scratch.current = Some(view_index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Entropy of the current window, scaled to [0, 1].
+    fn entropy(&self, scratch: &Scratch) -> f64 {
+        let n = self.window_size as f64;
+        ((n.log2() - scratch.weighted_log_sum / n) / 8.0).max(0.0)
+    }
+}
 
-            let discretized_entropy: usize = ((entropy * self.byte_color.len() as f64) as usize)
-                .clamp(0, self.byte_color.len() - 1);
+impl Style for Entropy {
+    fn color_at_index(&self, view: &View, view_index: u64, scratch: &mut Scratch) -> Color {
+        if self.move_to(view, view_index, scratch) {
+            let discretized_entropy: usize =
+                ((self.entropy(scratch) * self.byte_color.len() as f64) as usize)
+                    .clamp(0, self.byte_color.len() - 1);
             self.byte_color[discretized_entropy]
         } else {
             [0, 0, 0, 0]
         }
     }
 }
+
+#[test]
+fn entropy_sliding_window() {
+    let data: Vec<u8> = (0..4096u32).map(|i| (i * i / 7 % 251) as u8).collect();
+    for stride in [1, 3, 40] {
+        let view = View::new(&data, 0, 0, stride);
+        let entropy = Entropy::with_window_size(32);
+        let mut sliding = Scratch::default();
+        for view_index in 0..(4000 / stride) {
+            assert!(entropy.move_to(&view, view_index, &mut sliding));
+            let mut direct = Scratch::default();
+            assert!(entropy.move_to(&view, view_index, &mut direct));
+            assert!((entropy.entropy(&sliding) - entropy.entropy(&direct)).abs() < 1e-9);
+        }
+    }
+
+    let view = View::new(&data, 0, 4080, 1);
+    let entropy = Entropy::with_window_size(32);
+    assert!(!entropy.move_to(&view, 0, &mut Scratch::default()));
+}
@@ -1,64 +1,87 @@
 use std::convert::TryInto;
 
+use crate::datatype::BitOrder;
+
 pub struct View<'a> {
-    stride: isize,
-    start: isize,
+    stride: u64,
+    start: u64,
 
     data: &'a [u8],
+    /// Position of `data[0]` within the buffer
+    data_offset: u64,
 }
 
 impl<'a> View<'a> {
-    pub fn new(data: &'a [u8], start: isize, stride: isize) -> Self {
+    pub fn new(data: &'a [u8], data_offset: u64, start: u64, stride: u64) -> Self {
         assert!(stride >= 1);
 
         View {
             start,
             stride,
             data,
+            data_offset,
         }
     }
 
-    // pub fn len(&self) -> isize {
+    // pub fn len(&self) -> u64 {
     //     // the length of the view is (len - start)/stride, but rounded towards
     //     // infinity. that's what the "+ stride - 1" part is for.
-    //     (self.data.len() as isize - self.start + self.stride - 1) / self.stride
+    //     (self.data.len() as u64 - self.start + self.stride - 1) / self.stride
     // }
 
-    pub fn data_index(&self, view_index: isize) -> usize {
-        (self.start + view_index * self.stride)
-            .try_into()
-            .expect("positive index")
+    pub fn stride(&self) -> u64 {
+        self.stride
+    }
+
+    pub fn data_index(&self, view_index: u64) -> u64 {
+        self.start + view_index * self.stride
     }
 
-    pub fn byte_at(&self, view_index: isize) -> Option<u8> {
-        self.data.get(self.data_index(view_index)).copied()
+    pub fn byte_at(&self, view_index: u64) -> Option<u8> {
+        self.slice_at(view_index, 1).map(|slice| slice[0])
     }
 
-    pub fn be_u32_at(&self, view_index: isize) -> Option<u32> {
-        let data_index = self.data_index(view_index);
-        self.data
-            .get(data_index..(data_index + 4))
+    pub fn be_u32_at(&self, view_index: u64) -> Option<u32> {
+        self.slice_at(view_index, 4)
             .and_then(|slice| slice.try_into().ok())
             .map(u32::from_be_bytes)
     }
 
-    pub fn rgb_at(&self, view_index: isize) -> Option<[u8; 3]> {
-        let data_index = self.data_index(view_index);
-        self.data
-            .get(data_index..(data_index + 3))
+    pub fn rgb_at(&self, view_index: u64) -> Option<[u8; 3]> {
+        self.slice_at(view_index, 3)
             .and_then(|slice| slice.try_into().ok())
     }
 
-    pub fn slice_at(&self, view_index: isize, len: usize) -> Option<&[u8]> {
-        let data_index = self.data_index(view_index);
-        self.data.get(data_index..(data_index + len))
+    /// Read a single bit. This treats `start` and `stride` as positions in bits (rather than
+    /// bytes).
+    pub fn bit_at(&self, view_index: u64, order: BitOrder) -> Option<bool> {
+        let bit_index = self.data_index(view_index);
+        let index: usize = (bit_index / 8)
+            .checked_sub(self.data_offset)?
+            .try_into()
+            .ok()?;
+        let byte = self.data.get(index)?;
+        let shift = match order {
+            BitOrder::MsbFirst => 7 - bit_index % 8,
+            BitOrder::LsbFirst => bit_index % 8,
+        };
+        Some((byte >> shift) & 1 == 1)
+    }
+
+    pub fn slice_at(&self, view_index: u64, len: usize) -> Option<&[u8]> {
+        let index: usize = self
+            .data_index(view_index)
+            .checked_sub(self.data_offset)?
+            .try_into()
+            .ok()?;
+        self.data.get(index..(index.checked_add(len)?))
     }
 }
 
 #[test]
 fn view_basic_access() {
     let data: Vec<u8> = vec![0, 1, 2];
-    let view = View::new(&data, 0, 1);
+    let view = View::new(&data, 0, 0, 1);
 
     assert_eq!(view.byte_at(0), Some(0));
     assert_eq!(view.byte_at(1), Some(1));
@@ -71,7 +94,7 @@ fn view_basic_access() {
 fn view_access_with_offset() {
     let data: Vec<u8> = vec![0, 1, 2, 3, 4, 5];
     {
-        let view = View::new(&data, 2, 1);
+        let view = View::new(&data, 0, 2, 1);
 
         assert_eq!(view.byte_at(0), Some(2));
         assert_eq!(view.byte_at(1), Some(3));
@@ -81,14 +104,14 @@ fn view_access_with_offset() {
         assert_eq!(view.byte_at(5), None);
     }
     {
-        let view = View::new(&data, 5, 1);
+        let view = View::new(&data, 0, 5, 1);
 
         assert_eq!(view.byte_at(0), Some(5));
         assert_eq!(view.byte_at(1), None);
         assert_eq!(view.byte_at(2), None);
     }
     {
-        let view = View::new(&data, 6, 1);
+        let view = View::new(&data, 0, 6, 1);
 
         assert_eq!(view.byte_at(0), None);
         assert_eq!(view.byte_at(1), None);
@@ -99,7 +122,7 @@ fn view_access_with_offset() {
 fn view_access_with_stride() {
     let data: Vec<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
     {
-        let view = View::new(&data, 2, 3);
+        let view = View::new(&data, 0, 2, 3);
 
         assert_eq!(view.byte_at(0), Some(2));
         assert_eq!(view.byte_at(1), Some(5));
@@ -108,7 +131,7 @@ fn view_access_with_stride() {
         assert_eq!(view.byte_at(4), None);
     }
     {
-        let view = View::new(&data, 0, 2);
+        let view = View::new(&data, 0, 0, 2);
 
         assert_eq!(view.byte_at(0), Some(0));
         assert_eq!(view.byte_at(1), Some(2));
@@ -119,7 +142,7 @@ fn view_access_with_stride() {
         assert_eq!(view.byte_at(6), None);
     }
     {
-        let view = View::new(&data, 0, 3);
+        let view = View::new(&data, 0, 0, 3);
 
         assert_eq!(view.byte_at(0), Some(0));
         assert_eq!(view.byte_at(1), Some(3));
@@ -129,14 +152,14 @@ fn view_access_with_stride() {
         assert_eq!(view.byte_at(5), None);
     }
     {
-        let view = View::new(&data, 0, 9);
+        let view = View::new(&data, 0, 0, 9);
 
         assert_eq!(view.byte_at(0), Some(0));
         assert_eq!(view.byte_at(1), Some(9));
         assert_eq!(view.byte_at(2), None);
     }
     {
-        let view = View::new(&data, 0, 10);
+        let view = View::new(&data, 0, 0, 10);
 
         assert_eq!(view.byte_at(0), Some(0));
         assert_eq!(view.byte_at(1), None);
@@ -147,7 +170,7 @@ fn view_access_with_stride() {
 #[test]
 fn view_access_u32() {
     let data: Vec<u8> = vec![0x12, 0x34, 0x56, 0x78, 0xaa, 0xbb, 0xcc, 0xdd];
-    let view = View::new(&data, 0, 4);
+    let view = View::new(&data, 0, 0, 4);
 
     assert_eq!(view.byte_at(0), Some(0x12));
     assert_eq!(view.byte_at(1), Some(0xaa));
@@ -156,3 +179,43 @@ fn view_access_u32() {
     assert_eq!(view.be_u32_at(1), Some(0xaabbccdd));
     assert_eq!(view.be_u32_at(2), None);
 }
+
+#[test]
+fn view_access_with_data_offset() {
+    let data: Vec<u8> = vec![10, 11, 12, 13];
+    let view = View::new(&data, 10, 8, 1);
+
+    assert_eq!(view.byte_at(0), None);
+    assert_eq!(view.byte_at(1), None);
+    assert_eq!(view.byte_at(2), Some(10));
+    assert_eq!(view.byte_at(5), Some(13));
+    assert_eq!(view.byte_at(6), None);
+    assert_eq!(view.data_index(2), 10);
+}
+
+#[test]
+fn view_access_bits() {
+    let data: Vec<u8> = vec![0b1000_0001, 0b0110_0000];
+    {
+        let view = View::new(&data, 0, 0, 1);
+
+        assert_eq!(view.bit_at(0, BitOrder::MsbFirst), Some(true));
+        assert_eq!(view.bit_at(1, BitOrder::MsbFirst), Some(false));
+        assert_eq!(view.bit_at(7, BitOrder::MsbFirst), Some(true));
+        assert_eq!(view.bit_at(9, BitOrder::MsbFirst), Some(true));
+        assert_eq!(view.bit_at(11, BitOrder::MsbFirst), Some(false));
+        assert_eq!(view.bit_at(16, BitOrder::MsbFirst), None);
+
+        assert_eq!(view.bit_at(1, BitOrder::LsbFirst), Some(false));
+        assert_eq!(view.bit_at(13, BitOrder::LsbFirst), Some(true));
+    }
+    {
+        // Start at bit 7, read every third bit
+        let view = View::new(&data, 0, 7, 3);
+
+        assert_eq!(view.bit_at(0, BitOrder::MsbFirst), Some(true));
+        assert_eq!(view.bit_at(1, BitOrder::MsbFirst), Some(true));
+        assert_eq!(view.bit_at(2, BitOrder::MsbFirst), Some(false));
+        assert_eq!(view.bit_at(3, BitOrder::MsbFirst), None);
+    }
+}